@@ -0,0 +1,246 @@
+use crate::{source::ProcessInfo, utils::{self, ProcessError}};
+
+use std::collections::{HashMap, HashSet};
+
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PriorityClass {
+    Idle,
+    BelowNormal,
+    Normal,
+    AboveNormal,
+    High,
+    Realtime
+}
+
+/// What a rule does to a process it matches
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Action {
+    /// The exit code is only used on Windows
+    Terminate {
+        #[serde(default)]
+        exit_code: u32
+    },
+
+    Suspend,
+
+    Priority(PriorityClass),
+
+    /// Only let it run on these CPUs
+    Affinity(Vec<usize>),
+
+    /// Leave it alone, but say that it matched
+    Log
+}
+
+impl Default for Action {
+    fn default() -> Self {
+        Self::Terminate { exit_code: 0 }
+    }
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Terminate { exit_code } => write!(f, "terminate (exit code {exit_code})"),
+            Self::Suspend => write!(f, "suspend"),
+            Self::Priority(class) => write!(f, "set priority to {class:?}"),
+            Self::Affinity(cpus) => write!(f, "restrict to CPUs {cpus:?}"),
+            Self::Log => write!(f, "log")
+        }
+    }
+}
+
+/// Like the derived `Deserialize`, but also accepts a plain `"terminate"`
+pub fn deserialize_action<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Action, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Spec {
+        Action(Action),
+        Name(String)
+    }
+
+    match Spec::deserialize(deserializer)? {
+        Spec::Action(v) => Ok(v),
+        Spec::Name(v) if v == "terminate" => Ok(Action::default()),
+        Spec::Name(v) => Err(de::Error::custom(format!("unknown action `{v}`")))
+    }
+}
+
+/// Carries out actions. Lets the kill loop be run without touching real processes.
+pub trait ActionExecutor {
+    fn execute(&mut self, process: &ProcessInfo, action: &Action) -> Result<(), ProcessError>;
+
+    /// Everything that's running, to find the descendants of a process.
+    /// Only the name, pid, parent pid and start time need to be filled in.
+    fn processes(&mut self) -> Vec<ProcessInfo>;
+}
+
+/// Does it for real
+pub struct SystemExecutor;
+
+impl ActionExecutor for SystemExecutor {
+    fn execute(&mut self, process: &ProcessInfo, action: &Action) -> Result<(), ProcessError> {
+        let (name, pid) = (process.name.as_str(), process.pid);
+
+        match action {
+            Action::Terminate { exit_code } => utils::kill_process(name, pid, *exit_code),
+            Action::Suspend => utils::suspend_process(name, pid),
+            Action::Priority(class) => utils::set_priority(name, pid, *class),
+            Action::Affinity(cpus) => utils::set_affinity(name, pid, cpus),
+            Action::Log => Ok(())
+        }
+    }
+
+    fn processes(&mut self) -> Vec<ProcessInfo> {
+        utils::process_table()
+    }
+}
+
+/// Just remembers what it was asked to do
+#[cfg(test)]
+impl ActionExecutor for Vec<(ProcessInfo, Action)> {
+    fn execute(&mut self, process: &ProcessInfo, action: &Action) -> Result<(), ProcessError> {
+        self.push((process.clone(), action.clone()));
+        Ok(())
+    }
+
+    fn processes(&mut self) -> Vec<ProcessInfo> {
+        Vec::new()
+    }
+}
+
+/// Every descendant of `pid` in `processes`, children before their parents
+pub fn descendants(processes: &[ProcessInfo], pid: u32) -> Vec<ProcessInfo> {
+    let start_times: HashMap<u32, Option<u64>> = processes.iter().map(|p| (p.pid, p.start_time)).collect();
+
+    let mut children = HashMap::<u32, Vec<&ProcessInfo>>::new();
+    for process in processes {
+        // pid 0 is its own parent on Windows
+        if process.pid == process.parent_pid {
+            continue;
+        }
+
+        // a parent pid isn't cleared when the parent exits, so whatever has that pid now may have started
+        // after its supposed child. The same time is fine, a fork can land in the same clock tick.
+        // If either start time is unknown it can't be told, so it's left out.
+        let parent_start = start_times.get(&process.parent_pid).copied().flatten();
+        if process.start_time.zip(parent_start).is_some_and(|(child, parent)| child >= parent) {
+            children.entry(process.parent_pid).or_default().push(process);
+        }
+    }
+
+    // a dead parent's pid can be reused by one of its own descendants, so watch for cycles
+    let mut seen = HashSet::from([pid]);
+    let mut found = Vec::new();
+    let mut stack = vec![(pid, false)];
+
+    while let Some((current, visited)) = stack.pop() {
+        if visited {
+            if current != pid {
+                found.extend(processes.iter().find(|p| p.pid == current).cloned());
+            }
+
+            continue;
+        }
+
+        // come back to it once all of its children are done
+        stack.push((current, true));
+
+        for child in children.get(&current).into_iter().flatten() {
+            if seen.insert(child.pid) {
+                stack.push((child.pid, false));
+            }
+        }
+    }
+
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Started in pid order
+    fn process(pid: u32, parent_pid: u32) -> ProcessInfo {
+        ProcessInfo {
+            name: format!("{pid}.exe"),
+            pid,
+            parent_pid,
+            start_time: Some(pid.into()),
+            ..Default::default()
+        }
+    }
+
+    fn pids(processes: &[ProcessInfo]) -> Vec<u32> {
+        processes.iter().map(|p| p.pid).collect()
+    }
+
+    #[test]
+    fn children_before_parents() {
+        //  1 -> 2 -> 4
+        //    -> 3 -> 5 -> 6
+        let processes = [process(1, 0), process(2, 1), process(3, 1), process(4, 2), process(5, 3), process(6, 5), process(7, 0)];
+        let found = pids(&descendants(&processes, 1));
+
+        assert_eq!(found.len(), 5);
+
+        let at = |pid: u32| found.iter().position(|&v| v == pid).unwrap();
+        assert!(at(4) < at(2));
+        assert!(at(6) < at(5) && at(5) < at(3));
+    }
+
+    #[test]
+    fn no_descendants() {
+        assert!(descendants(&[process(1, 0), process(2, 0)], 1).is_empty());
+        assert!(descendants(&[], 1).is_empty());
+    }
+
+    #[test]
+    fn stale_parents() {
+        // 3's parent exited, and 1 got its pid afterwards
+        let processes = [process(1, 0), process(2, 1), ProcessInfo { start_time: Some(0), ..process(3, 1) }];
+        assert_eq!(pids(&descendants(&processes, 1)), vec![2]);
+    }
+
+    #[test]
+    fn same_start_time() {
+        let processes = [process(1, 0), ProcessInfo { start_time: Some(1), ..process(2, 1) }];
+        assert_eq!(pids(&descendants(&processes, 1)), vec![2]);
+    }
+
+    #[test]
+    fn unknown_start_times() {
+        let unknown = |v: ProcessInfo| ProcessInfo { start_time: None, ..v };
+
+        assert!(descendants(&[process(1, 0), unknown(process(2, 1))], 1).is_empty());
+        assert!(descendants(&[unknown(process(1, 0)), process(2, 1)], 1).is_empty());
+    }
+
+    #[test]
+    fn cycles_end() {
+        // 1's parent died and 3 got its pid, all in the same clock tick
+        let processes = [process(1, 3), process(2, 1), process(3, 2)].map(|v| ProcessInfo { start_time: Some(1), ..v });
+        assert_eq!(pids(&descendants(&processes, 1)), vec![3, 2]);
+        assert_eq!(pids(&descendants(&processes, 3)), vec![2, 1]);
+
+        // pid 0 is its own parent on Windows
+        assert_eq!(pids(&descendants(&[process(0, 0), process(4, 0)], 0)), vec![4]);
+    }
+
+    #[test]
+    fn actions() {
+        let action = |v: serde_json::Value| deserialize_action(v).unwrap();
+
+        assert_eq!(action(serde_json::json!("terminate")), Action::Terminate { exit_code: 0 });
+        assert_eq!(action(serde_json::json!({ "terminate": { "exit_code": 3 } })), Action::Terminate { exit_code: 3 });
+        assert_eq!(action(serde_json::json!("suspend")), Action::Suspend);
+        assert_eq!(action(serde_json::json!({ "priority": "below_normal" })), Action::Priority(PriorityClass::BelowNormal));
+        assert_eq!(action(serde_json::json!({ "affinity": [0, 2] })), Action::Affinity(vec![0, 2]));
+        assert!(deserialize_action(serde_json::json!("explode")).is_err());
+    }
+}
@@ -0,0 +1,142 @@
+use thiserror::Error;
+
+
+/// Printed along with anything wrong with the arguments
+pub const USAGE: &str = "\
+Usage: process-killer [--hide] [--dry-run] [--trace]
+       process-killer upgrade-config
+       process-killer check [--schema]";
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ArgsError {
+    #[error("Unknown argument {0}")]
+    Unknown(String),
+
+    #[error("Only one of upgrade-config and check can be given")]
+    TwoCommands,
+
+    #[error("--schema only goes with check")]
+    SchemaWithoutCheck
+}
+
+/// What to do, instead of watching for processes
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    #[default]
+    Watch,
+
+    /// `upgrade-config`: rewrite the config as the current version, then exit
+    UpgradeConfig,
+
+    /// `check`: say everything that's wrong with the config, then exit
+    Check
+}
+
+/// Command line flags
+#[derive(Debug, Default)]
+pub struct Args {
+    pub command: Command,
+
+    /// `--hide` / `-h`: hide the console
+    pub hide: bool,
+
+    /// `--dry-run`: say what would be done to each process, but don't do it
+    pub dry_run: bool,
+
+    /// `--trace`: get told about new processes as they start instead of polling for them, on Windows
+    pub trace: bool,
+
+    /// `--schema`: with `check`, print a JSON Schema for the config instead
+    pub schema: bool
+}
+
+impl Args {
+    /// Exits with a usage message if the arguments are wrong
+    pub fn parse() -> Self {
+        match Self::parse_from(std::env::args().skip(1)) {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("{e}\n\n{USAGE}");
+                std::process::exit(2);
+            }
+        }
+    }
+
+    pub fn parse_from(args: impl IntoIterator<Item = String>) -> Result<Self, ArgsError> {
+        let mut parsed = Self::default();
+
+        for arg in args {
+            match arg.as_str() {
+                // --help used to hide the console too, so keep it working for anyone relying on it
+                "-h" | "--hide" | "--help" => parsed.hide = true,
+                "--dry-run" => parsed.dry_run = true,
+                "--trace" => parsed.trace = true,
+                "--schema" => parsed.schema = true,
+                "upgrade-config" => parsed.set_command(Command::UpgradeConfig)?,
+                "check" => parsed.set_command(Command::Check)?,
+                _ => return Err(ArgsError::Unknown(arg))
+            }
+        }
+
+        if parsed.schema && parsed.command != Command::Check {
+            return Err(ArgsError::SchemaWithoutCheck);
+        }
+
+        Ok(parsed)
+    }
+
+    fn set_command(&mut self, command: Command) -> Result<(), ArgsError> {
+        if self.command != Command::Watch {
+            return Err(ArgsError::TwoCommands);
+        }
+
+        self.command = command;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, ArgsError> {
+        Args::parse_from(args.iter().map(|v| v.to_string()))
+    }
+
+    #[test]
+    fn flags() {
+        let args = parse(&["--dry-run", "-h", "--trace"]).unwrap();
+
+        assert_eq!(args.command, Command::Watch);
+        assert!(args.hide && args.dry_run && args.trace && !args.schema);
+        assert!(parse(&["--help"]).unwrap().hide);
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(parse(&[]).unwrap().command, Command::Watch);
+        assert_eq!(parse(&["upgrade-config"]).unwrap().command, Command::UpgradeConfig);
+
+        let args = parse(&["--schema", "check"]).unwrap();
+        assert_eq!(args.command, Command::Check);
+        assert!(args.schema);
+    }
+
+    #[test]
+    fn unknown_arguments() {
+        assert_eq!(parse(&["--dry-rn"]).unwrap_err(), ArgsError::Unknown("--dry-rn".to_string()));
+        assert_eq!(parse(&["check", "config.toml"]).unwrap_err(), ArgsError::Unknown("config.toml".to_string()));
+    }
+
+    #[test]
+    fn one_command() {
+        assert_eq!(parse(&["check", "upgrade-config"]).unwrap_err(), ArgsError::TwoCommands);
+        assert_eq!(parse(&["check", "check"]).unwrap_err(), ArgsError::TwoCommands);
+    }
+
+    #[test]
+    fn schema_needs_check() {
+        assert_eq!(parse(&["--schema"]).unwrap_err(), ArgsError::SchemaWithoutCheck);
+        assert_eq!(parse(&["upgrade-config", "--schema"]).unwrap_err(), ArgsError::SchemaWithoutCheck);
+    }
+}
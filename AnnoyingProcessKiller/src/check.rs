@@ -0,0 +1,874 @@
+use crate::{
+    config::{self, ConfigError, Data, Format, VERSION},
+    matcher::{Pattern, PatternError, PatternSpec},
+    rules::{Rule, RuleEntry, Ruleset},
+    schema
+};
+
+use std::{collections::HashMap, error::Error, fmt, io::Read};
+
+use serde_json::{Map, Value};
+
+
+/// The settings a condition can match patterns on
+const PATTERN_FIELDS: &[&str] = &["name", "path", "cmdline", "parent_name", "user"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The config won't load
+    Error,
+    /// It loads, but probably doesn't do what was meant
+    Warning
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning")
+        }
+    }
+}
+
+/// Something wrong with a config, and where it is
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Like `rules[1].match.name`, empty for the whole config
+    pub path: String,
+    pub message: String,
+    /// How it could be fixed
+    pub help: Option<String>,
+    /// Line and column, both counting from 1. Only known for JSON, or for syntax errors.
+    pub position: Option<(usize, usize)>
+}
+
+/// Everything found wrong with one config file
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub file: String,
+    pub diagnostics: Vec<Diagnostic>
+}
+
+impl Report {
+    pub fn has_errors(&self) -> bool {
+        self.count(Severity::Error) > 0
+    }
+
+    fn count(&self, severity: Severity) -> usize {
+        self.diagnostics.iter().filter(|d| d.severity == severity).count()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for diagnostic in &self.diagnostics {
+            write!(f, "{}", self.file)?;
+            if let Some((line, column)) = diagnostic.position {
+                write!(f, ":{line}:{column}")?;
+            }
+            // like the lines of a regex error
+            let message = diagnostic.message.replace('\n', "\n    ");
+            writeln!(f, ": {}: {message}", diagnostic.severity)?;
+
+            if let Some(help) = &diagnostic.help {
+                writeln!(f, "    help: {help}")?;
+            }
+        }
+
+        let plural = |n: usize, what: &str| format!("{n} {what}{}", if n == 1 { "" } else { "s" });
+
+        match (self.count(Severity::Error), self.count(Severity::Warning)) {
+            (0, 0) => writeln!(f, "{} looks fine", self.file),
+            (errors, warnings) => writeln!(f, "{}: {} and {}", self.file, plural(errors, "error"), plural(warnings, "warning"))
+        }
+    }
+}
+
+/// Check the config at `path` as thoroughly as possible, finding everything wrong with it rather than
+/// stopping at the first thing like loading it does
+pub fn check(path: &str) -> Report {
+    let mut checker = Checker {
+        report: Report {
+            file: path.to_string(),
+            diagnostics: Vec::new()
+        },
+        text: String::new(),
+        offsets: HashMap::new(),
+        legacy: 0
+    };
+
+    checker.run(path);
+
+    // in the order they're in the file, like reading it top to bottom
+    checker.report.diagnostics.sort_by_key(|d| d.position.unwrap_or((usize::MAX, 0)));
+    checker.report
+}
+
+struct Checker {
+    report: Report,
+    /// The file, with comments blanked out if it's JSONC
+    text: String,
+    /// Where each setting starts in `text`, by path. Only found for JSON.
+    offsets: HashMap<String, usize>,
+    /// How many rules came from a version 1 `processes` list, which are the first ones once it's migrated
+    legacy: usize
+}
+
+impl Checker {
+    fn run(&mut self, path: &str) {
+        let format = match Format::of(path) {
+            Ok(v) => v,
+            Err(e) => return self.error("", e.to_string(), None)
+        };
+
+        let text = match std::fs::read_to_string(path) {
+            Ok(v) => v,
+            Err(e) => return self.error("", format!("can't be read: {e}"), None)
+        };
+
+        self.text = match format {
+            // comments are blanked out rather than removed, so positions stay the same
+            Format::Jsonc => {
+                let mut stripped = String::new();
+                match json_comments::StripComments::new(text.as_bytes()).read_to_string(&mut stripped) {
+                    Ok(_) => stripped,
+                    Err(_) => text
+                }
+            }
+
+            _ => text
+        };
+
+        let mut config = match format.parse(&self.text) {
+            Ok(v) => v,
+            Err(e) => return self.syntax_error(e.as_ref())
+        };
+
+        if matches!(format, Format::Json | Format::Jsonc) {
+            self.offsets = Locator::locate(&self.text);
+        }
+
+        let had_version = config.get("version").is_some();
+        if !had_version {
+            self.legacy = config.get("processes").and_then(Value::as_array).map_or(0, Vec::len);
+        }
+
+        let version = match config::migrate(&mut config) {
+            Ok(v) => v,
+            Err(e) => {
+                let path = match e {
+                    ConfigError::InvalidVersion(_) | ConfigError::NewerVersion(_) => "version",
+                    ConfigError::InvalidProcesses(_) | ConfigError::ProcessesRemoved => "processes",
+                    _ => ""
+                };
+
+                return self.error(path, e.to_string(), None);
+            }
+        };
+
+        if version < VERSION {
+            self.warning(
+                if had_version { "version" } else { "" },
+                format!("this is version {version} of the config, it still works but the current one is {VERSION}"),
+                Some("`process-killer upgrade-config` rewrites it as the current version".to_string())
+            );
+        }
+
+        let schema = schema::schema();
+        self.unknown_fields(&schema, &schema, &config, "");
+
+        // the rules that are fine on their own, so they can still be checked against each other
+        let mut entries: HashMap<&str, Vec<RuleEntry>> = HashMap::new();
+
+        for list in ["rules", "allow"] {
+            match config.get(list) {
+                Some(Value::Array(rules)) => {
+                    for (i, rule) in rules.iter().enumerate() {
+                        if let Some(rule) = self.rule(rule, &format!("{list}[{i}]")) {
+                            entries.entry(list).or_default().push(RuleEntry::new(list, i, rule));
+                        }
+                    }
+                }
+
+                Some(_) => self.error(list, format!("{list} has to be a list of rules"), None),
+                None => ()
+            }
+        }
+
+        match config.get("poll_interval") {
+            Some(Value::Number(v)) => {
+                let seconds = v.as_f64().unwrap_or_default();
+                if !config::valid_poll_interval(seconds) {
+                    self.error("poll_interval", ConfigError::InvalidPollInterval(seconds).to_string(), None);
+                }
+            }
+
+            Some(v) => self.error("poll_interval", format!("poll_interval has to be a number of seconds, not {v}"), None),
+            None => ()
+        }
+
+        if config.get("$schema").is_some_and(|v| !v.is_string()) {
+            self.error("$schema", "$schema has to be a path or URL in quotes", None);
+        }
+
+        let mut ruleset = Ruleset::new(entries.remove("allow").unwrap_or_default(), entries.remove("rules").unwrap_or_default());
+
+        for (path, e) in config::rule_problems(&ruleset) {
+            self.error(&path, e.to_string(), None);
+        }
+
+        // an empty condition would make every rule after it look redundant
+        for list in [&mut ruleset.allow, &mut ruleset.kill, &mut ruleset.thresholds] {
+            list.retain(|e| !self.has_error_in(&e.path));
+        }
+
+        self.redundant(&ruleset);
+
+        // anything the checks above missed still shows up when it's loaded for real
+        if !self.report.has_errors() {
+            if let Err(e) = serde_json::from_value::<Data>(config) {
+                self.error("", e.to_string(), None);
+            }
+        }
+    }
+
+    fn push(&mut self, severity: Severity, path: &str, message: impl Into<String>, help: Option<String>) {
+        let path = self.source(path);
+        let position = self.position(&path);
+
+        self.report.diagnostics.push(Diagnostic {
+            severity,
+            path,
+            message: message.into(),
+            help,
+            position
+        });
+    }
+
+    fn error(&mut self, path: &str, message: impl Into<String>, help: Option<String>) {
+        self.push(Severity::Error, path, message, help);
+    }
+
+    fn warning(&mut self, path: &str, message: impl Into<String>, help: Option<String>) {
+        self.push(Severity::Warning, path, message, help);
+    }
+
+    /// Whether there's already an error in `path` or anything in it
+    fn has_error_in(&self, path: &str) -> bool {
+        let path = self.source(path);
+
+        self.report.diagnostics.iter().any(|d| {
+            d.severity == Severity::Error && d.path.strip_prefix(&path).is_some_and(|rest| rest.is_empty() || rest.starts_with(['.', '[']))
+        })
+    }
+
+    /// Where `path` in the migrated config is in the file itself, which differs for rules that came from `processes`
+    fn source(&self, path: &str) -> String {
+        let index = path.strip_prefix("rules[")
+            .and_then(|v| v.split_once(']'))
+            .and_then(|(i, rest)| Some((i.parse::<usize>().ok()?, rest)));
+
+        match index {
+            // all there is of these is the name
+            Some((i, _)) if i < self.legacy => format!("processes[{i}]"),
+            Some((i, rest)) if self.legacy > 0 => format!("rules[{}]{rest}", i - self.legacy),
+            _ => path.to_string()
+        }
+    }
+
+    /// The line and column of `path`, or of the closest thing around it that can be found
+    fn position(&self, path: &str) -> Option<(usize, usize)> {
+        let mut path = path;
+
+        loop {
+            if let Some(offset) = self.offsets.get(path) {
+                return Some(line_column(&self.text, *offset));
+            }
+
+            path = &path[..path.rfind(['.', '['])?];
+        }
+    }
+
+    fn syntax_error(&mut self, error: &(dyn Error + 'static)) {
+        let position = if let Some(e) = error.downcast_ref::<serde_json::Error>() {
+            Some((e.line(), e.column()))
+        } else if let Some(e) = error.downcast_ref::<toml::de::Error>() {
+            e.line_col().map(|(line, column)| (line + 1, column + 1))
+        } else if let Some(e) = error.downcast_ref::<serde_yaml::Error>() {
+            e.location().map(|v| (v.line(), v.column()))
+        } else {
+            None
+        };
+
+        // the position is shown on its own already
+        let message = error.to_string();
+        let message = match position {
+            Some((line, column)) => message.trim_end_matches(&format!(" at line {line} column {column}")).to_string(),
+            None => message
+        };
+
+        self.report.diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            path: String::new(),
+            message: format!("not valid: {message}"),
+            help: None,
+            position
+        });
+    }
+
+    /// Report every setting the schema doesn't know about, suggesting the closest one it does
+    fn unknown_fields(&mut self, root: &Value, schema: &Value, value: &Value, path: &str) {
+        let schema = resolve(root, schema);
+
+        match value {
+            Value::Object(map) => {
+                let branches = object_branches(root, schema);
+                let shared = |properties: &Map<String, Value>| map.keys().filter(|k| properties.contains_key(*k)).count();
+
+                // when it could be one of a few kinds of object, go by the one it looks most like
+                let best = match branches.iter().max_by_key(|v| shared(v)) {
+                    Some(v) => *v,
+                    None => return
+                };
+
+                let known: Vec<&str> = if shared(best) == 0 {
+                    branches.iter().flat_map(|v| v.keys()).map(String::as_str).collect()
+                } else {
+                    best.keys().map(String::as_str).collect()
+                };
+
+                for (key, value) in map {
+                    let child = join(path, key);
+
+                    match best.get(key) {
+                        Some(schema) => self.unknown_fields(root, schema, value, &child),
+                        None => {
+                            let help = suggest(key, &known);
+                            let message = match path {
+                                "" => format!("unknown setting `{key}`"),
+                                v => format!("unknown setting `{key}` in {}", self.source(v))
+                            };
+
+                            self.error(&child, message, Some(help));
+                        }
+                    }
+                }
+            }
+
+            Value::Array(items) => {
+                if let Some(schema) = schema.get("items") {
+                    for (i, item) in items.iter().enumerate() {
+                        self.unknown_fields(root, schema, item, &format!("{path}[{i}]"));
+                    }
+                }
+            }
+
+            _ => ()
+        }
+    }
+
+    /// Check a rule's patterns one by one, then everything else about it
+    /// The rule, if nothing is wrong with it
+    fn rule(&mut self, rule: &Value, path: &str) -> Option<Rule> {
+        if let Some(condition) = rule.get("match") {
+            self.patterns(condition, &join(path, "match"));
+        }
+
+        if self.has_error_in(path) {
+            return None;
+        }
+
+        match serde_json::from_value::<Rule>(rule.clone()) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                self.error(path, format!("{} is invalid: {e}", self.source(path)), None);
+                None
+            }
+        }
+    }
+
+    fn patterns(&mut self, condition: &Value, path: &str) {
+        let condition = match condition.as_object() {
+            Some(v) => v,
+            None => return
+        };
+
+        for field in PATTERN_FIELDS {
+            let value = match condition.get(*field) {
+                Some(v) => v,
+                None => continue
+            };
+
+            let path = join(path, field);
+            let spec = match serde_json::from_value::<PatternSpec>(value.clone()) {
+                Ok(v) => v,
+                Err(_) => {
+                    let help = "write a name or a glob in quotes, `{ \"glob\": ... }` or `{ \"regex\": ... }`".to_string();
+                    self.error(&path, format!("{} isn't a pattern: {value}", self.source(&path)), Some(help));
+                    continue;
+                }
+            };
+
+            if let Err(e) = Pattern::new(spec) {
+                let help = match e {
+                    PatternError::UnclosedClass { .. } => Some("close it with `]`, or write `[[]` to match a `[`".to_string()),
+                    PatternError::InvalidRegex { .. } => None
+                };
+
+                self.error(&path, format!("{} is an invalid pattern: {e}", self.source(&path)), help);
+            }
+        }
+
+        for list in ["all", "any"] {
+            if let Some(Value::Array(conditions)) = condition.get(list) {
+                for (i, condition) in conditions.iter().enumerate() {
+                    self.patterns(condition, &format!("{path}.{list}[{i}]"));
+                }
+            }
+        }
+    }
+
+    /// Rules that are the same as another, or that never get to decide anything because another rule always does first
+    fn redundant(&mut self, ruleset: &Ruleset) {
+
+        // what a rule does, without the id which is only for the log
+        let key = |entry: &RuleEntry| {
+            let mut rule = entry.rule.clone();
+            rule.id = None;
+            serde_json::to_value(rule).ok()
+        };
+
+        let mut ids: HashMap<&str, &RuleEntry> = HashMap::new();
+
+        for list in [&ruleset.allow, &ruleset.kill, &ruleset.thresholds] {
+            for entry in list {
+                if let Some(id) = entry.rule.id.as_deref() {
+                    if let Some(first) = ids.get(id) {
+                        let message = format!("{} has the same id as {}", self.source(&entry.path), self.source(&first.path));
+                        self.warning(&join(&entry.path, "id"), message, Some("give it its own id, so the log says which one acted".to_string()));
+                    }
+                    ids.entry(id).or_insert(entry);
+                }
+            }
+        }
+
+        for (kind, list) in [("allow", &ruleset.allow), ("kill", &ruleset.kill), ("threshold", &ruleset.thresholds)] {
+            for (i, entry) in list.iter().enumerate() {
+                if let Some(first) = list[..i].iter().find(|v| key(v) == key(entry)) {
+                    let message = format!("{} is the same as {}", self.source(&entry.path), self.source(&first.path));
+                    self.warning(&entry.path, message, Some("remove one of them".to_string()));
+                    continue;
+                }
+
+                let covers = |v: &&RuleEntry| v.rule.condition.covers(&entry.rule.condition);
+
+                // allow rules are checked before anything else, including thresholds
+                let allow = match kind {
+                    "allow" => None,
+                    _ => ruleset.allow.iter().find(covers)
+                };
+
+                if let Some(allow) = allow {
+                    let allow = self.source(&allow.path);
+                    let message = format!("{} never acts, {allow} allows everything it matches", self.source(&entry.path));
+                    self.warning(&entry.path, message, Some(format!("make {allow} more specific, or remove this rule")));
+                    continue;
+                }
+
+                // threshold rules each keep their own time, so an earlier one doesn't stop a later one
+                if kind == "threshold" {
+                    continue;
+                }
+
+                if let Some(earlier) = list[..i].iter().find(covers) {
+                    let (earlier, this) = (self.source(&earlier.path), self.source(&entry.path));
+                    let message = format!("{this} never decides anything, {earlier} matches everything it does first");
+                    self.warning(&entry.path, message, Some(format!("move it above {earlier}, or remove it")));
+                }
+            }
+        }
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    match path {
+        "" => key.to_string(),
+        v => format!("{v}.{key}")
+    }
+}
+
+/// Follow a `$ref` like `#/definitions/rule`
+fn resolve<'a>(root: &'a Value, schema: &'a Value) -> &'a Value {
+    schema.get("$ref")
+        .and_then(Value::as_str)
+        .and_then(|v| v.strip_prefix('#'))
+        .and_then(|v| root.pointer(v))
+        .unwrap_or(schema)
+}
+
+/// The properties of every kind of object `schema` allows
+fn object_branches<'a>(root: &'a Value, schema: &'a Value) -> Vec<&'a Map<String, Value>> {
+    let schema = resolve(root, schema);
+
+    if let Some(Value::Object(properties)) = schema.get("properties") {
+        return vec![properties];
+    }
+
+    match schema.get("anyOf") {
+        Some(Value::Array(branches)) => branches.iter().flat_map(|v| object_branches(root, v)).collect(),
+        _ => Vec::new()
+    }
+}
+
+/// The closest of `known` to `key` if it's close enough to be a typo, or else all of them
+fn suggest(key: &str, known: &[&str]) -> String {
+    let closest = known.iter()
+        .map(|v| (edit_distance(&key.to_lowercase(), &v.to_lowercase()), v))
+        .min_by_key(|(distance, _)| *distance);
+
+    match closest {
+        Some((distance, v)) if distance <= (key.chars().count() / 3).max(1) => format!("did you mean `{v}`?"),
+        _ => {
+            let known: Vec<String> = known.iter().map(|v| format!("`{v}`")).collect();
+            format!("it has to be one of {}", known.join(", "))
+        }
+    }
+}
+
+/// How many characters have to be added, removed, changed or swapped with the next one to turn `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+
+    // distances[i][j] is between the first i characters of `a` and the first j of `b`
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    distances[0] = (0..=b.len()).collect();
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let changed = usize::from(a[i - 1] != b[j - 1]);
+
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + changed);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+
+            distances[i][j] = distance;
+        }
+    }
+
+    distances[a.len()][b.len()]
+}
+
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap_or_default().chars().count() + 1;
+
+    (line, column)
+}
+
+/// Finds where every setting starts in a JSON document that's already known to be valid.
+/// For settings in an object that's the key, for items in a list it's the item.
+struct Locator<'a> {
+    text: &'a str,
+    pos: usize,
+    offsets: HashMap<String, usize>
+}
+
+impl<'a> Locator<'a> {
+    fn locate(text: &'a str) -> HashMap<String, usize> {
+        let mut locator = Self {
+            text,
+            pos: 0,
+            offsets: HashMap::new()
+        };
+
+        locator.value(String::new());
+        locator.offsets
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn value(&mut self, path: String) {
+        self.skip_whitespace();
+        self.offsets.entry(path.clone()).or_insert(self.pos);
+
+        match self.peek() {
+            Some(b'{') => {
+                self.pos += 1;
+
+                loop {
+                    self.skip_whitespace();
+
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b'"') => {
+                            let start = self.pos;
+                            let key = join(&path, &self.string());
+                            self.offsets.insert(key.clone(), start);
+
+                            // the colon
+                            self.skip_whitespace();
+                            self.pos += 1;
+
+                            self.value(key);
+                        }
+
+                        // the closing brace, or something that isn't JSON after all
+                        _ => {
+                            self.pos += 1;
+                            break;
+                        }
+                    }
+                }
+            }
+
+            Some(b'[') => {
+                self.pos += 1;
+                let mut index = 0;
+
+                loop {
+                    self.skip_whitespace();
+
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') | None => {
+                            self.pos += 1;
+                            break;
+                        }
+
+                        _ => {
+                            self.value(format!("{path}[{index}]"));
+                            index += 1;
+                        }
+                    }
+                }
+            }
+
+            Some(b'"') => {
+                self.string();
+            }
+
+            // numbers, true, false and null
+            _ => {
+                while self.peek().is_some_and(|c| !c.is_ascii_whitespace() && !matches!(c, b',' | b'}' | b']')) {
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    /// Read a string, leaving `pos` after its closing quote
+    fn string(&mut self) -> String {
+        let start = self.pos;
+        self.pos += 1;
+
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+
+            match c {
+                b'\\' => self.pos += 1,
+                b'"' => break,
+                _ => ()
+            }
+        }
+
+        let raw = &self.text[start..self.pos.min(self.text.len())];
+        serde_json::from_str(raw).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check `text` as if it were a config file called `name`
+    fn check_text(name: &str, text: &str) -> Report {
+        let path = std::env::temp_dir().join(format!("process-killer-{}-{name}", std::process::id()));
+        std::fs::write(&path, text).unwrap();
+
+        let report = check(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        report
+    }
+
+    fn find<'a>(report: &'a Report, message: &str) -> &'a Diagnostic {
+        report.diagnostics.iter()
+            .find(|d| d.message.contains(message))
+            .unwrap_or_else(|| panic!("nothing says `{message}` in {:#?}", report.diagnostics))
+    }
+
+    #[test]
+    fn nested_keys_have_their_line_and_column() {
+        let report = check_text("nested.json", r#"{
+    "version": 2,
+    "rules": [
+        { "match": { "name": "a.exe" } },
+        { "match": { "any": [{ "nmae": "b.exe" }] } }
+    ]
+}"#);
+
+        let diagnostic = find(&report, "unknown setting `nmae`");
+        assert_eq!(diagnostic.severity, Severity::Error);
+        assert_eq!(diagnostic.path, "rules[1].match.any[0].nmae");
+        assert_eq!(diagnostic.position, Some((5, 32)));
+        assert_eq!(diagnostic.help.as_deref(), Some("did you mean `name`?"));
+    }
+
+    #[test]
+    fn jsonc_comments_keep_positions() {
+        let report = check_text("comments.jsonc", r#"{
+    // "rules": [{ "nmae": "x" }]
+    "version": 2, /* two */ "poll_interval": "2"
+}"#);
+
+        let diagnostic = find(&report, "poll_interval has to be a number");
+        assert_eq!(diagnostic.position, Some((3, 29)));
+        assert_eq!(report.diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn suggestions() {
+        let known = ["name", "path", "cmdline", "parent_name"];
+
+        assert_eq!(suggest("nmae", &known), "did you mean `name`?");
+        assert_eq!(suggest("Path", &known), "did you mean `path`?");
+        assert_eq!(suggest("parentname", &known), "did you mean `parent_name`?");
+        assert_eq!(suggest("exe", &known), "it has to be one of `name`, `path`, `cmdline`, `parent_name`");
+
+        assert_eq!(edit_distance("nmae", "name"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn legacy_processes_keep_their_index() {
+        let report = check_text("legacy.json", r#"{
+    "processes": ["a.exe", "b.exe"],
+    "rules": [
+        { "match": { "name": "c.exe" }, "tree": "yes" }
+    ]
+}"#);
+
+        let version = find(&report, "this is version 1");
+        assert_eq!((version.severity, version.path.as_str()), (Severity::Warning, ""));
+
+        // it's the third rule once migrated, but the first one in the file
+        let invalid = find(&report, "is invalid");
+        assert_eq!(invalid.path, "rules[0]");
+        assert!(invalid.message.starts_with("rules[0] is invalid"));
+        assert_eq!(invalid.position, Some((4, 9)));
+    }
+
+    #[test]
+    fn legacy_duplicates_point_at_processes() {
+        let report = check_text("duplicates.json", r#"{ "processes": ["a.exe", "a.exe"] }"#);
+
+        let same = find(&report, "is the same as");
+        assert_eq!(same.path, "processes[1]");
+        assert_eq!(same.message, "processes[1] is the same as processes[0]");
+        assert_eq!(same.position, Some((1, 26)));
+    }
+
+    #[test]
+    fn redundant_rules() {
+        let report = check_text("redundant.json", r#"{
+    "version": 2,
+    "rules": [
+        { "id": "updates", "match": { "name": "*update*.exe" } },
+        { "id": "updates", "match": { "name": "winupdate.exe" } },
+        { "match": { "name": "a.exe" } },
+        { "match": { "name": "a.exe" }, "id": "again" },
+        { "match": { "path": "C:\\Vendor\\*" } }
+    ],
+    "allow": [
+        { "match": { "path": "C:\\Vendor\\*" } }
+    ]
+}"#);
+
+        assert!(!report.has_errors(), "{report}");
+
+        let id = find(&report, "has the same id as");
+        assert_eq!((id.path.as_str(), id.message.as_str()), ("rules[1].id", "rules[1] has the same id as rules[0]"));
+
+        let earlier = find(&report, "never decides anything");
+        assert_eq!(earlier.message, "rules[1] never decides anything, rules[0] matches everything it does first");
+        assert_eq!(earlier.position, Some((5, 9)));
+
+        // the id is only for the log
+        let same = find(&report, "is the same as");
+        assert_eq!(same.message, "rules[3] is the same as rules[2]");
+
+        let allowed = find(&report, "never acts");
+        assert_eq!(allowed.message, "rules[4] never acts, allow[0] allows everything it matches");
+        assert_eq!(allowed.help.as_deref(), Some("make allow[0] more specific, or remove this rule"));
+
+        assert_eq!(report.diagnostics.len(), 4);
+    }
+
+    #[test]
+    fn errors_dont_hide_other_problems() {
+        let report = check_text("everything.json", r#"{
+    "version": 2,
+    "rules": [
+        { "match": { "name": "a.exe" }, "actoin": "log" },
+        { "match": {} },
+        { "match": { "name": "b.exe" }, "threshold": { "cpu": -1 } },
+        { "match": { "name": "c.exe" } },
+        { "match": { "name": "c.exe" } }
+    ],
+    "poll_interval": 0
+}"#);
+
+        let paths: Vec<(Severity, &str)> = report.diagnostics.iter().map(|d| (d.severity, d.path.as_str())).collect();
+        assert_eq!(paths, [
+            (Severity::Error, "rules[0].actoin"),
+            (Severity::Error, "rules[1].match"),
+            (Severity::Error, "rules[2].threshold"),
+            (Severity::Warning, "rules[4]"),
+            (Severity::Error, "poll_interval")
+        ]);
+    }
+
+    #[test]
+    fn yaml_has_no_positions() {
+        let report = check_text("config.yaml", "version: 2\nrules:\n  - match: { name: a.exe, nmae: b.exe }\n");
+
+        let diagnostic = find(&report, "unknown setting `nmae`");
+        assert_eq!(diagnostic.path, "rules[0].match.nmae");
+        assert_eq!(diagnostic.position, None);
+    }
+
+    #[test]
+    fn syntax_errors_have_a_position() {
+        let report = check_text("broken.toml", "version = 2\n[[rules]\n");
+
+        assert!(report.has_errors());
+        assert_eq!(report.diagnostics[0].position.map(|(line, _)| line), Some(2));
+    }
+
+    #[test]
+    fn line_columns() {
+        let text = "ab\ncdé\nf";
+
+        assert_eq!(line_column(text, 0), (1, 1));
+        assert_eq!(line_column(text, 3), (2, 1));
+        assert_eq!(line_column(text, text.find('f').unwrap()), (3, 1));
+        assert_eq!(line_column(text, text.len() - 2), (2, 4));
+    }
+}
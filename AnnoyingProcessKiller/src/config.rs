@@ -0,0 +1,581 @@
+use crate::{matcher::Pattern, rules::{Rule, RuleEntry, Ruleset}};
+
+use std::{error::Error, path::Path, time::Duration};
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use thiserror::Error;
+
+
+/// The version of the config this writes, and the newest one it can read
+pub const VERSION: u64 = 2;
+
+/// Checking more often than this would only burn CPU
+pub const MIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Anything that starts is left alone for up to this long already
+pub const MAX_POLL_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Where the config is looked for, in this order, if there's more than one
+pub const PATHS: &[&str] = &["config.json", "config.jsonc", "config.toml", "config.yaml", "config.yml"];
+
+lazy_static! {
+    pub(crate) static ref DEFAULT: &'static str = r#"
+{
+    "version": 2,
+    "rules": [
+        { "id": "CompatTelRunner.exe", "match": { "name": "CompatTelRunner.exe" } }
+    ],
+    "poll_interval": 2
+}
+"#.trim_start();
+}
+
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("{rule} has an empty condition, which would match every process")]
+    EmptyCondition {
+        rule: String
+    },
+
+    #[error("{rule}'s threshold {problem}")]
+    InvalidThreshold {
+        rule: String,
+        problem: &'static str
+    },
+
+    #[error(
+        "poll_interval has to be between {} and {} seconds, not {0:?}",
+        MIN_POLL_INTERVAL.as_secs_f64(),
+        MAX_POLL_INTERVAL.as_secs_f64()
+    )]
+    InvalidPollInterval(f64),
+
+    #[error("{0} has to end in .json, .jsonc, .toml, .yaml or .yml")]
+    UnknownFormat(String),
+
+    #[error("the config has to be a map of settings, like {{ \"version\": {VERSION}, \"rules\": [] }}")]
+    NotAMap,
+
+    #[error("version has to be a whole number, not {0}")]
+    InvalidVersion(Value),
+
+    #[error("version {0} is newer than this program understands, which is up to {VERSION}")]
+    NewerVersion(u64),
+
+    #[error("processes has to be a list, not {0}")]
+    InvalidProcesses(Value),
+
+    #[error("processes was replaced by rules in version 2, use {{ \"match\": {{ \"name\": ... }} }} rules instead")]
+    ProcessesRemoved
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Data {
+    /// Where editors can find the schema, from `process-killer check --schema`
+    #[serde(rename = "$schema", default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<String>,
+
+    /// Always [`VERSION`] once it's been migrated
+    pub version: u64,
+
+    /// Kill anything matching one of these
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<Rule>,
+
+    /// Never kill anything matching one of these, even if a kill rule matches
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<Rule>,
+
+    /// Seconds between checks for new processes, when they have to be polled for
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval: f64
+}
+
+fn default_poll_interval() -> f64 {
+    2.0
+}
+
+impl Data {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        match self.problems().into_iter().next() {
+            Some((_, e)) => Err(e),
+            None => Ok(())
+        }
+    }
+
+    /// Everything that makes this unusable, along with where it is, like `rules[1]`
+    pub fn problems(&self) -> Vec<(String, ConfigError)> {
+        let mut problems = Vec::new();
+
+        if !valid_poll_interval(self.poll_interval) {
+            problems.push(("poll_interval".to_string(), ConfigError::InvalidPollInterval(self.poll_interval)));
+        }
+
+        problems.extend(rule_problems(&self.ruleset()));
+
+        problems
+    }
+
+    /// What's different in `new`, one line for each thing added, removed or changed
+    pub fn changes(&self, new: &Data) -> Vec<String> {
+        let mut changes = Vec::new();
+
+        list_changes(&mut changes, "rules", &self.rules, &new.rules);
+        list_changes(&mut changes, "allow", &self.allow, &new.allow);
+
+        if self.poll_interval != new.poll_interval {
+            changes.push(format!("~ poll_interval: {} -> {}", self.poll_interval, new.poll_interval));
+        }
+
+        changes
+    }
+
+    /// Kept within the bounds even if this hasn't been validated
+    pub fn poll_interval(&self) -> Duration {
+        match Duration::try_from_secs_f64(self.poll_interval) {
+            Ok(v) => v.clamp(MIN_POLL_INTERVAL, MAX_POLL_INTERVAL),
+            // too big to be a Duration at all
+            Err(_) if self.poll_interval > 0.0 => MAX_POLL_INTERVAL,
+            Err(_) => MIN_POLL_INTERVAL
+        }
+    }
+
+    pub fn ruleset(&self) -> Ruleset {
+        let entries = |list: &str, rules: &[Rule]| -> Vec<RuleEntry> {
+            rules.iter().enumerate().map(|(i, r)| RuleEntry::new(list, i, r.clone())).collect()
+        };
+
+        Ruleset::new(entries("allow", &self.allow), entries("rules", &self.rules))
+    }
+}
+
+/// Whether this many seconds fits between [`MIN_POLL_INTERVAL`] and [`MAX_POLL_INTERVAL`]
+pub fn valid_poll_interval(seconds: f64) -> bool {
+    Duration::try_from_secs_f64(seconds).is_ok_and(|v| (MIN_POLL_INTERVAL..=MAX_POLL_INTERVAL).contains(&v))
+}
+
+/// Everything wrong with the rules themselves, so it can be checked even when the rest of the config isn't usable
+pub fn rule_problems(ruleset: &Ruleset) -> Vec<(String, ConfigError)> {
+    let mut problems = Vec::new();
+
+    let all = || ruleset.allow.iter().chain(&ruleset.kill).chain(&ruleset.thresholds);
+
+    for entry in all().filter(|e| e.rule.condition.has_empty()) {
+        problems.push((format!("{}.match", entry.path), ConfigError::EmptyCondition { rule: entry.label.clone() }));
+    }
+
+    for entry in ruleset.allow.iter().filter(|e| e.rule.threshold.is_some()) {
+        let problem = "does nothing on an allow rule";
+        problems.push((format!("{}.threshold", entry.path), ConfigError::InvalidThreshold { rule: entry.label.clone(), problem }));
+    }
+
+    for entry in &ruleset.thresholds {
+        if let Some(problem) = entry.rule.threshold.as_ref().and_then(|v| v.problem()) {
+            problems.push((format!("{}.threshold", entry.path), ConfigError::InvalidThreshold { rule: entry.label.clone(), problem }));
+        }
+    }
+
+    problems
+}
+
+/// Compare two versions of a list as JSON, so a changed entry shows up as removed and then added
+fn list_changes<T: Serialize>(changes: &mut Vec<String>, list: &str, old: &[T], new: &[T]) {
+    let json = |items: &[T]| -> Vec<String> {
+        items.iter().map(|v| serde_json::to_string(v).unwrap_or_default()).collect()
+    };
+
+    let mut added = json(new);
+    let mut removed = Vec::new();
+
+    for item in json(old) {
+        match added.iter().position(|v| *v == item) {
+            Some(i) => {
+                added.remove(i);
+            }
+            None => removed.push(item)
+        }
+    }
+
+    changes.extend(removed.into_iter().map(|v| format!("- {list}: {v}")));
+    changes.extend(added.into_iter().map(|v| format!("+ {list}: {v}")));
+}
+
+/// The first config in [`PATHS`] that exists, or where the default one goes if none do
+pub fn find() -> &'static str {
+    PATHS.iter().copied().find(|v| Path::new(v).exists()).unwrap_or(PATHS[0])
+}
+
+/// What a config is written in, going by its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    /// JSON with `//` and `/* */` comments
+    Jsonc,
+    Toml,
+    Yaml
+}
+
+impl Format {
+    pub fn of(path: &str) -> Result<Self, ConfigError> {
+        let extension = Path::new(path).extension().and_then(|v| v.to_str()).unwrap_or_default();
+
+        match extension.to_ascii_lowercase().as_str() {
+            "json" => Ok(Format::Json),
+            "jsonc" => Ok(Format::Jsonc),
+            "toml" => Ok(Format::Toml),
+            "yaml" | "yml" => Ok(Format::Yaml),
+            _ => Err(ConfigError::UnknownFormat(path.to_string()))
+        }
+    }
+
+    /// Everything is read into JSON, so the rest only has to deal with one format
+    pub fn parse(self, text: &str) -> Result<Value, Box<dyn Error>> {
+        Ok(match self {
+            Format::Json => serde_json::from_str(text)?,
+            // comments are blanked out rather than removed, so errors still point at the right column
+            Format::Jsonc => serde_json::from_reader(json_comments::StripComments::new(text.as_bytes()))?,
+            Format::Toml => toml::from_str(text)?,
+            Format::Yaml => serde_yaml::from_str(text)?
+        })
+    }
+
+    pub fn write(self, value: &Value) -> Result<String, Box<dyn Error>> {
+        Ok(match self {
+            Format::Json | Format::Jsonc => {
+                let mut json = Vec::new();
+                let mut serializer = serde_json::Serializer::with_formatter(&mut json, serde_json::ser::PrettyFormatter::with_indent(b"    "));
+                value.serialize(&mut serializer)?;
+
+                String::from_utf8(json)? + "\n"
+            }
+
+            // going through toml's own Value puts tables after everything else, which TOML needs
+            Format::Toml => toml::to_string_pretty(&toml::Value::try_from(value)?)?,
+            Format::Yaml => serde_yaml::to_string(value)?
+        })
+    }
+}
+
+/// Bring a config up to [`VERSION`], returning the version it was.
+/// Anything without a version is version 1, from before there was one.
+pub fn migrate(config: &mut Value) -> Result<u64, ConfigError> {
+    let map = config.as_object_mut().ok_or(ConfigError::NotAMap)?;
+
+    let version = match map.get("version") {
+        None => 1,
+        Some(v) => v.as_u64().filter(|v| *v > 0).ok_or_else(|| ConfigError::InvalidVersion(v.clone()))?
+    };
+
+    if version > VERSION {
+        return Err(ConfigError::NewerVersion(version));
+    }
+
+    if version < 2 {
+        from_v1(map)?;
+    } else if map.contains_key("processes") {
+        return Err(ConfigError::ProcessesRemoved);
+    }
+
+    // version goes first, so it's the first thing anyone sees
+    if version < VERSION {
+        map.shift_remove("version");
+        *config = Value::Object(std::iter::once(("version".to_string(), json!(VERSION))).chain(std::mem::take(map)).collect());
+    }
+
+    Ok(version)
+}
+
+/// `processes` become kill rules that only match the name, ahead of `rules` like they were checked before
+fn from_v1(map: &mut Map<String, Value>) -> Result<(), ConfigError> {
+    let processes = match map.shift_remove("processes") {
+        Some(Value::Array(v)) => v,
+        Some(v) => return Err(ConfigError::InvalidProcesses(v)),
+        None => Vec::new()
+    };
+
+    let mut rules: Vec<Value> = processes.into_iter().map(|name| {
+        // the id is what the log showed for these before. A bad pattern is left for deserializing to complain about.
+        match serde_json::from_value::<Pattern>(name.clone()) {
+            Ok(pattern) => json!({ "id": pattern.to_string(), "match": { "name": name } }),
+            Err(_) => json!({ "match": { "name": name } })
+        }
+    }).collect();
+
+    match map.get_mut("rules") {
+        Some(Value::Array(v)) => {
+            rules.append(v);
+            *v = rules;
+        }
+
+        // anything else is left for deserializing to complain about
+        Some(_) => (),
+
+        None if rules.is_empty() => (),
+        None => {
+            map.insert("rules".to_string(), Value::Array(rules));
+        }
+    }
+
+    Ok(())
+}
+
+/// Read the config at `path`, writing out the default one first if there isn't one
+pub fn load(path: &str) -> Result<Data, Box<dyn Error>> {
+    if std::fs::metadata(path).is_err() {
+        std::fs::write(path, DEFAULT.as_bytes()).expect("Failed to write file");
+    }
+
+    read(path)
+}
+
+/// Read the config at `path` as it's written, before it's migrated
+fn read_value(path: &str) -> Result<(Format, Value), Box<dyn Error>> {
+    let format = Format::of(path)?;
+    let text = std::fs::read_to_string(path)?;
+
+    Ok((format, format.parse(&text)?))
+}
+
+/// Read, migrate and validate the config at `path`. An old version is only migrated in memory, see [`upgrade`].
+pub fn read(path: &str) -> Result<Data, Box<dyn Error>> {
+    let (_, mut config) = read_value(path)?;
+
+    let version = migrate(&mut config)?;
+    if version < VERSION {
+        println!("Warning: {path} is version {version} of the config, `process-killer upgrade-config` will update it to {VERSION}");
+    }
+
+    // patterns are compiled here, so a bad one is caught right away
+    let data: Data = serde_json::from_value(config)?;
+    data.validate()?;
+
+    Ok(data)
+}
+
+/// Rewrite the config at `path` as the current version, keeping a copy of the old one next to it.
+/// Returns whether there was anything to do.
+pub fn upgrade(path: &str) -> Result<bool, Box<dyn Error>> {
+    let (format, mut config) = read_value(path)?;
+
+    let version = migrate(&mut config)?;
+
+    // never write out something that won't load
+    let data: Data = serde_json::from_value(config.clone())?;
+    data.validate()?;
+
+    if version == VERSION {
+        println!("{path} is already version {VERSION}");
+        return Ok(false);
+    }
+
+    let backup = format!("{path}.v{version}.bak");
+    std::fs::copy(path, &backup)?;
+    std::fs::write(path, format.write(&config)?)?;
+
+    println!("Upgraded {path} from version {version} to {VERSION}, the old one is in {backup}");
+    if format == Format::Jsonc {
+        println!("Warning: Comments aren't kept, they're only in {backup} now");
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_interval(poll_interval: f64) -> Data {
+        Data {
+            schema: None,
+            version: VERSION,
+            rules: Vec::new(),
+            allow: Vec::new(),
+            poll_interval
+        }
+    }
+
+    #[test]
+    fn poll_interval_bounds() {
+        for v in [0.1, 0.5, 2.0, 3600.0] {
+            assert!(with_interval(v).validate().is_ok(), "{v}");
+        }
+
+        for v in [0.0, -1.0, 0.05, 3600.5, 1e300, f64::NAN, f64::INFINITY] {
+            assert!(matches!(with_interval(v).validate(), Err(ConfigError::InvalidPollInterval(_))), "{v}");
+        }
+    }
+
+    #[test]
+    fn poll_interval_is_never_zero() {
+        assert_eq!(with_interval(1e300).poll_interval(), MAX_POLL_INTERVAL);
+        assert_eq!(with_interval(f64::INFINITY).poll_interval(), MAX_POLL_INTERVAL);
+        assert_eq!(with_interval(0.0).poll_interval(), MIN_POLL_INTERVAL);
+        assert_eq!(with_interval(-1.0).poll_interval(), MIN_POLL_INTERVAL);
+        assert_eq!(with_interval(f64::NAN).poll_interval(), MIN_POLL_INTERVAL);
+        assert_eq!(with_interval(2.5).poll_interval(), Duration::from_millis(2500));
+    }
+
+    fn v1() -> Value {
+        json!({
+            "processes": ["CompatTelRunner.exe", "*telemetry*.exe"],
+            "rules": [{ "match": { "name": "updater.exe" }, "action": "suspend" }],
+            "poll_interval": 0.5
+        })
+    }
+
+    fn v2() -> Value {
+        json!({
+            "version": 2,
+            "rules": [
+                { "id": "CompatTelRunner.exe", "match": { "name": "CompatTelRunner.exe" } },
+                { "id": "*telemetry*.exe", "match": { "name": "*telemetry*.exe" } },
+                { "match": { "name": "updater.exe" }, "action": "suspend" }
+            ],
+            "poll_interval": 0.5
+        })
+    }
+
+    /// A path for `name` that no other test uses
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("process-killer-{}-{name}", std::process::id()));
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn migrates_v1() {
+        let mut config = v1();
+
+        assert_eq!(migrate(&mut config).unwrap(), 1);
+        assert_eq!(config, v2());
+        // serde_json keeps the order, so this also checks version went first
+        assert_eq!(config.as_object().unwrap().keys().next().map(String::as_str), Some("version"));
+        assert!(serde_json::from_value::<Data>(config).unwrap().validate().is_ok());
+    }
+
+    #[test]
+    fn migrates_only_processes() {
+        let mut config = json!({ "processes": ["a.exe"] });
+
+        assert_eq!(migrate(&mut config).unwrap(), 1);
+        assert_eq!(config, json!({ "version": 2, "rules": [{ "id": "a.exe", "match": { "name": "a.exe" } }] }));
+    }
+
+    #[test]
+    fn current_version_is_left_alone() {
+        let mut config = v2();
+
+        assert_eq!(migrate(&mut config).unwrap(), VERSION);
+        assert_eq!(config, v2());
+    }
+
+    #[test]
+    fn bad_versions() {
+        let migrated = |mut config: Value| migrate(&mut config);
+
+        assert!(matches!(migrated(json!({ "version": 3 })), Err(ConfigError::NewerVersion(3))));
+        assert!(matches!(migrated(json!({ "version": 0 })), Err(ConfigError::InvalidVersion(_))));
+        assert!(matches!(migrated(json!({ "version": "2" })), Err(ConfigError::InvalidVersion(_))));
+        assert!(matches!(migrated(json!({ "version": 2, "processes": [] })), Err(ConfigError::ProcessesRemoved)));
+        assert!(matches!(migrated(json!({ "processes": "a.exe" })), Err(ConfigError::InvalidProcesses(_))));
+        assert!(matches!(migrated(json!([])), Err(ConfigError::NotAMap)));
+    }
+
+    #[test]
+    fn formats_round_trip() {
+        for format in [Format::Json, Format::Jsonc, Format::Toml, Format::Yaml] {
+            let text = format.write(&v2()).unwrap();
+
+            assert_eq!(format.parse(&text).unwrap(), v2(), "{format:?}:\n{text}");
+            assert!(text.trim_start_matches("---\n").starts_with(match format {
+                Format::Json | Format::Jsonc => "{\n    \"version\": 2",
+                Format::Toml => "version = 2\n",
+                Format::Yaml => "version: 2\n"
+            }), "{format:?}:\n{text}");
+        }
+    }
+
+    #[test]
+    fn jsonc_comments() {
+        let text = "{\n    // old\n    \"version\": 2, /* two */\n    \"rules\": []\n}";
+        assert_eq!(Format::Jsonc.parse(text).unwrap(), json!({ "version": 2, "rules": [] }));
+        assert!(Format::Json.parse(text).is_err());
+    }
+
+    #[test]
+    fn formats_by_extension() {
+        assert_eq!(Format::of("config.json").unwrap(), Format::Json);
+        assert_eq!(Format::of("config.JSONC").unwrap(), Format::Jsonc);
+        assert_eq!(Format::of("dir/config.toml").unwrap(), Format::Toml);
+        assert_eq!(Format::of("config.yml").unwrap(), Format::Yaml);
+        assert!(matches!(Format::of("config.ini"), Err(ConfigError::UnknownFormat(_))));
+    }
+
+    #[test]
+    fn upgrades_each_format() {
+        for (name, format) in [("upgrade.json", Format::Json), ("upgrade.jsonc", Format::Jsonc), ("upgrade.toml", Format::Toml), ("upgrade.yaml", Format::Yaml)] {
+            let path = temp_path(name);
+            let backup = format!("{path}.v1.bak");
+            let old = format.write(&v1()).unwrap();
+            std::fs::write(&path, &old).unwrap();
+
+            assert!(upgrade(&path).unwrap(), "{format:?}");
+            assert_eq!(std::fs::read_to_string(&backup).unwrap(), old);
+            assert_eq!(read_value(&path).unwrap(), (format, v2()));
+            assert_eq!(read(&path).unwrap().rules.len(), 3);
+
+            // there's nothing left to do the second time
+            assert!(!upgrade(&path).unwrap(), "{format:?}");
+
+            std::fs::remove_file(&path).unwrap();
+            std::fs::remove_file(&backup).unwrap();
+        }
+    }
+
+    #[test]
+    fn invalid_configs_arent_upgraded() {
+        let path = temp_path("invalid.json");
+        let old = r#"{ "processes": ["a.exe"], "rules": [{ "match": {} }] }"#;
+        std::fs::write(&path, old).unwrap();
+
+        assert!(upgrade(&path).is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), old);
+        assert!(std::fs::metadata(format!("{path}.v1.bak")).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn changes() {
+        let old = serde_json::from_value::<Data>(v2()).unwrap();
+        let new = serde_json::from_value::<Data>(json!({
+            "version": 2,
+            "rules": [
+                { "id": "CompatTelRunner.exe", "match": { "name": "CompatTelRunner.exe" } },
+                { "match": { "name": "updater.exe" }, "action": "log" },
+                { "match": { "name": "new.exe" } }
+            ],
+            "allow": [{ "match": { "name": "ok.exe" } }],
+            "poll_interval": 1
+        })).unwrap();
+
+        let changes = old.changes(&new);
+        let expected = [
+            ("- rules: ", r#""id":"*telemetry*.exe""#),
+            ("- rules: ", r#""action":"suspend""#),
+            ("+ rules: ", r#""action":"log""#),
+            ("+ rules: ", r#""name":"new.exe""#),
+            ("+ allow: ", r#""name":"ok.exe""#),
+            ("~ poll_interval: ", "0.5 -> 1")
+        ];
+
+        assert_eq!(changes.len(), expected.len(), "{changes:#?}");
+        for (change, (start, has)) in changes.iter().zip(expected) {
+            assert!(change.starts_with(start) && change.contains(has), "{change} should be {start}...{has}...");
+        }
+
+        assert!(old.changes(&old).is_empty());
+    }
+}
@@ -1,101 +1,735 @@
 mod utils;
+mod source;
+mod matcher;
+mod rules;
+mod config;
+mod actions;
+mod args;
+mod threshold;
+mod monitor;
+mod reload;
+mod check;
+mod schema;
 
-use WMI_Query;
-use tokio::select;
-use WMI_Query::Win32_Process::Win32_Process;
+use actions::{ActionExecutor, SystemExecutor};
+use args::{Args, Command};
+use config::{Data, Format};
+#[cfg(windows)]
+use matcher::Pattern;
+use monitor::{Breach, Monitor, SAMPLE_INTERVAL};
+use reload::Reason;
+use rules::{Decision, RuleEntry, Ruleset};
+use source::ProcessInfo;
+use source::{ProcessEventSource, SourceError};
+#[cfg(windows)]
+use source::{WmiSource, WmiUsage};
+#[cfg(target_os = "linux")]
+use source::{NetlinkSource, ProcSource, ProcUsage};
+use tokio::{select, sync::mpsc::Receiver};
 
-use core::convert::From;
 use std::{error::Error};
 
-use lazy_static::lazy_static;
-use serde::{Deserialize, Serialize};
-
+#[cfg(windows)]
 use windows::Win32::System::SystemServices::SE_DEBUG_NAME;
 
-use ctrlc;
-
 
-lazy_static! {
-    static ref DEFAULT: &'static str = r#"
-{
-    "processes": [
-        "CompatTelRunner.exe"
-    ]
-}
-"#.trim_start();
-}
+/// Makes the event source for a config, since some of them depend on it
+type MakeSource<'a> = dyn Fn(&Data) -> Box<dyn ProcessEventSource> + 'a;
 
-
-#[derive(Serialize, Deserialize, Debug)]
-struct Data {
-    processes: Vec<String>
-}
+/// How many times in a row the event source can stop on its own and be restarted, before giving up
+const MAX_RESTARTS: u32 = 3;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    // hide console
-    let args: Vec<String> = std::env::args().collect();
-    if let Some(v) = args.get(1) {
-        if v == "-h" || v == "--help" {
-            utils::hide_console();
+    let args = Args::parse();
+    let path = config::find();
+
+    match args.command {
+        Command::Watch => (),
+
+        Command::UpgradeConfig => {
+            config::upgrade(path)?;
+            return Ok(());
+        }
+
+        Command::Check if args.schema => {
+            print!("{}", Format::Json.write(&schema::schema())?);
+            return Ok(());
+        }
+
+        Command::Check => {
+            let report = check::check(path);
+            print!("{report}");
+
+            if report.has_errors() {
+                std::process::exit(1);
+            }
+            return Ok(());
         }
     }
 
-    // this privilege is required to kill SYSTEM processes
-    // It requires Admin, but we enforce that in the manifest build.rs
-    utils::set_privilege(SE_DEBUG_NAME, true)?;
+    #[cfg(windows)]
+    {
+        if args.hide {
+            utils::hide_console();
+        }
 
-    let json = std::fs::read_to_string("config.json").unwrap_or_else(|_| {
-        std::fs::write("config.json", DEFAULT.as_bytes()).expect("Failed to write file");
-        DEFAULT.to_string()
-    });
+        // this privilege is required to kill SYSTEM processes
+        // It requires Admin, but we enforce that in the manifest build.rs
+        utils::set_privilege(SE_DEBUG_NAME, true)?;
+    }
 
-    let mut data: Data = serde_json::from_str(&json)?;
+    let data = match config::load(path) {
+        Ok(v) => v,
+        Err(e) => {
+            invalid(path, e.as_ref());
+            std::process::exit(1);
+        }
+    };
+
+    if args.dry_run {
+        println!("Dry run, nothing will be touched");
+    }
 
     let (tx, mut rx) = tokio::sync::mpsc::channel(1);
 
     ctrlc::set_handler(move || tx.try_send(()).expect("Could not send signal on channel."))
         .expect("Error setting Ctrl-C handler");
 
-    // lowercase all of the entries
-    for process in &mut data.processes.iter_mut() {
-        *process = process.to_lowercase();
+    #[cfg(windows)]
+    let make_source = |data: &Data| -> Box<dyn ProcessEventSource> {
+        // anything no kill rule could match can be left out, as long as they all go by name
+        let names: Vec<Pattern> = data.ruleset().kill_names()
+            .map(|v| v.into_iter().cloned().collect())
+            .unwrap_or_default();
+
+        let source = WmiSource::with_filter(data.poll_interval(), &names);
+        Box::new(if args.trace { source.with_trace() } else { source })
+    };
+
+    // the proc connector sees everything immediately, but needs root
+    #[cfg(target_os = "linux")]
+    let make_source = |data: &Data| -> Box<dyn ProcessEventSource> {
+        match NetlinkSource::open() {
+            Ok(v) => Box::new(v),
+            Err(e) => {
+                println!("Proc connector unavailable ({e}), polling /proc instead");
+                Box::new(ProcSource::with_interval(data.poll_interval()))
+            }
+        }
+    };
+
+    #[cfg(target_os = "linux")]
+    if args.trace {
+        println!("Warning: --trace only does anything on Windows");
+    }
+
+    let reloads = reload::triggers(path);
+
+    run(&make_source, &mut SystemExecutor, data, path, reloads, args.dry_run, &mut rx).await?;
+
+    Ok(())
+}
+
+/// Everything that depends on the config, so a new one can be swapped in all at once
+struct Watching {
+    data: Data,
+    ruleset: Ruleset,
+    source: Box<dyn ProcessEventSource>,
+    events: async_channel::Receiver<ProcessInfo>,
+    /// Only kept so it keeps sampling
+    _monitor: Option<Monitor>,
+    breaches: async_channel::Receiver<Breach>
+}
+
+impl Watching {
+    fn start(data: Data, make_source: &MakeSource) -> Result<Self, Box<dyn Error>> {
+        let ruleset = data.ruleset();
+
+        // threshold rules need usage sampled, which nothing else does
+        let (_no_monitor, mut breaches) = async_channel::unbounded();
+        let monitor = if ruleset.thresholds.is_empty() {
+            None
+        } else {
+            #[cfg(windows)]
+            let (monitor, rx) = Monitor::start(WmiUsage::new, ruleset.clone(), SAMPLE_INTERVAL)?;
+            #[cfg(target_os = "linux")]
+            let (monitor, rx) = Monitor::start(|| Ok(ProcUsage::default()), ruleset.clone(), SAMPLE_INTERVAL)?;
+
+            breaches = rx;
+            Some(monitor)
+        };
+
+        let mut source = make_source(&data);
+        let events = source.start()?;
+
+        Ok(Self {
+            data,
+            ruleset,
+            source,
+            events,
+            _monitor: monitor,
+            breaches
+        })
     }
 
-    let wmi_con = WMI_Query::WMIConnection::new()?;
-    let rx2 = wmi_con.exec_notification_query_async(
-        "SELECT * FROM __InstanceCreationEvent WITHIN 2 WHERE TargetInstance ISA 'Win32_Process'"
-    )?;
+    /// Swap in a new source for the same config
+    fn restart_source(&mut self, make_source: &MakeSource) -> Result<(), Box<dyn Error>> {
+        self.source.stop();
+
+        let mut source = make_source(&self.data);
+        self.events = source.start()?;
+        self.source = source;
+
+        Ok(())
+    }
+}
+
+/// Act on anything disallowed that the source reports, and anything that the monitor says has stayed over
+/// a threshold rule's limits, until a shutdown signal comes in. With `dry_run` nothing is executed, only logged.
+/// The config is read again from `path` whenever `reloads` says so.
+async fn run(
+    make_source: &MakeSource<'_>,
+    executor: &mut dyn ActionExecutor,
+    data: Data,
+    path: &str,
+    reloads: async_channel::Receiver<Reason>,
+    dry_run: bool,
+    shutdown: &mut Receiver<()>
+) -> Result<(), Box<dyn Error>> {
+    let mut watching = Watching::start(data, make_source)?;
+    sweep(&mut watching, executor, dry_run)?;
+
+    let mut restarts = 0;
 
     loop {
         select! {
             // ctrl c break
-            _ = rx.recv() => break,
-
-            Ok(Ok(process)) = rx2.recv() => {
-                //println!("got process {:?}", process);
-
-                //println!("all properties: {:?}", process.get_property_names());
-                //println!("property: {:?}", process.get_property("TargetInstance"));
-                let inst = process.get_embedded_object("TargetInstance")?;
-                //println!("embedded {:?}", inst);
-                //println!("embedded props {:?}", inst.get_property_names());
-                //println!("process name: {:?}", inst.get_property("Name"));
-                //println!("properties! {:?}", inst.get_properties(false));
-                //println!("");
-
-                let res = Win32_Process::from(inst);
-                println!("Started {}, {}", res.Name, res.ProcessId);
-                if data.processes.contains(&res.Name.to_lowercase()) {
-                    println!("{} is disallowed! Killed!", res.Name);
-                    utils::kill_process(&res.Name, res.ProcessId as u32)?;
-                } else {
-                    println!("{} is allowed", res.Name);
-                }
+            _ = shutdown.recv() => break,
+
+            event = watching.events.recv() => {
+                let process = match event {
+                    Ok(v) => v,
+
+                    // the source stopped without being told to, and nothing that starts would be seen anymore
+                    Err(_) => {
+                        restarts += 1;
+                        if restarts > MAX_RESTARTS {
+                            return Err(Box::new(SourceError::KeptStopping(MAX_RESTARTS)));
+                        }
+
+                        println!("Warning: The event source stopped, restarting it\n");
+                        watching.restart_source(make_source)?;
+
+                        if let Err(e) = sweep(&mut watching, executor, dry_run) {
+                            println!("Warning: Failed to check what's already running: {e}");
+                        }
+                        continue;
+                    }
+                };
+
+                restarts = 0;
+                println!("Started {}, {}", process.name, process.pid);
+                handle(executor, &watching.ruleset, &process, dry_run);
+            }
+
+            Ok((process, rule)) = watching.breaches.recv() => {
+                println!("{} ({}) has been over {rule}'s threshold", process.name, process.pid);
+                apply(executor, &rule, &process, dry_run);
                 println!();
             }
+
+            Ok(reason) = reloads.recv() => {
+                let new = match reload(&watching.data, path, reason, make_source) {
+                    Some(v) => v,
+                    None => continue
+                };
+
+                // the new source is already running, so nothing starts unseen in between
+                let mut old = std::mem::replace(&mut watching, new);
+                old.source.stop();
+                restarts = 0;
+
+                if let Err(e) = sweep(&mut watching, executor, dry_run) {
+                    println!("Warning: Failed to check what's already running: {e}");
+                }
+            }
+        }
+    }
+
+    watching.source.stop();
+
+    Ok(())
+}
+
+/// Check everything that was already running when the source started, which it never reports as started
+fn sweep(watching: &mut Watching, executor: &mut dyn ActionExecutor, dry_run: bool) -> Result<(), Box<dyn Error>> {
+    for process in watching.source.running()? {
+        // don't act on ourselves, and don't log everything else that's running
+        if process.pid == std::process::id() || matches!(watching.ruleset.evaluate(&process), Decision::NoMatch) {
+            continue;
         }
+
+        println!("Already running {}, {}", process.name, process.pid);
+        handle(executor, &watching.ruleset, &process, dry_run);
     }
 
     Ok(())
 }
+
+/// Read the config again and start watching with it.
+/// Nothing comes back if it's invalid, can't be started or hasn't changed, so the last good one is kept.
+fn reload(current: &Data, path: &str, reason: Reason, make_source: &MakeSource) -> Option<Watching> {
+    let data = match config::read(path) {
+        Ok(v) => v,
+        Err(e) => {
+            println!("Warning: Keeping the last good config");
+            invalid(path, e.as_ref());
+            println!();
+            return None;
+        }
+    };
+
+    let changes = current.changes(&data);
+    if changes.is_empty() {
+        println!("Read {path} again because {reason}, nothing changed\n");
+        return None;
+    }
+
+    match Watching::start(data, make_source) {
+        Ok(v) => {
+            println!("Reloaded {path} because {reason}:");
+            for change in changes {
+                println!("  {change}");
+            }
+            println!();
+
+            Some(v)
+        }
+
+        Err(e) => {
+            println!("Warning: Keeping the last good config, the new one couldn't be started: {e}\n");
+            None
+        }
+    }
+}
+
+/// Say why the config at `path` couldn't be loaded. `check` finds everything wrong and where it is,
+/// instead of just the first thing that stopped it loading.
+fn invalid(path: &str, error: &dyn Error) {
+    let report = check::check(path);
+
+    if report.has_errors() {
+        print!("{report}");
+    } else {
+        println!("{path} is invalid: {error}");
+    }
+}
+
+/// Check `process` against the rules, and act on it if it's disallowed
+fn handle(executor: &mut dyn ActionExecutor, ruleset: &Ruleset, process: &ProcessInfo, dry_run: bool) {
+    match ruleset.evaluate(process) {
+        Decision::Kill(rule) => apply(executor, rule, process, dry_run),
+
+        Decision::Allow(rule) => println!("{} is allowed by {rule}", process.name),
+
+        Decision::NoMatch => println!("{} is allowed", process.name)
+    }
+    println!();
+}
+
+/// Apply `rule`'s action to `process`, and its descendants if the rule says so
+fn apply(executor: &mut dyn ActionExecutor, rule: &RuleEntry, process: &ProcessInfo, dry_run: bool) {
+    if rule.rule.tree {
+        // children first, so nothing gets respawned by a parent that's still around
+        for child in actions::descendants(&executor.processes(), process.pid) {
+            act(executor, rule, &child, dry_run);
+        }
+    }
+
+    act(executor, rule, process, dry_run);
+}
+
+/// Apply `rule`'s action to a single process
+fn act(executor: &mut dyn ActionExecutor, rule: &RuleEntry, process: &ProcessInfo, dry_run: bool) {
+    let action = &rule.rule.action;
+    if dry_run || rule.rule.audit {
+        println!("Would {action} {} ({}, {rule})", process.name, process.pid);
+    } else {
+        match executor.execute(process, action) {
+            Ok(()) => println!("{} is disallowed by {rule}! Action: {action}", process.name),
+            // it may have exited on its own already, which is no reason to stop watching
+            Err(e) => println!("{} is disallowed by {rule}, but {action} failed: {e}", process.name)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actions::{Action, PriorityClass};
+    use utils::ProcessError;
+
+    use std::cell::Cell;
+
+    use serde_json::json;
+
+    fn data(config: serde_json::Value) -> Data {
+        serde_json::from_value(config).unwrap()
+    }
+
+    /// Kills `bad.exe` unless it was started by `good.exe`
+    fn config() -> Data {
+        data(json!({
+            "version": config::VERSION,
+            "rules": [{ "match": { "name": "bad.exe" } }],
+            "allow": [{ "match": { "parent_name": "good.exe" } }]
+        }))
+    }
+
+    fn process(name: &str, pid: u32) -> ProcessInfo {
+        ProcessInfo {
+            name: name.to_string(),
+            pid,
+            ..Default::default()
+        }
+    }
+
+    fn pids(done: &[(ProcessInfo, Action)]) -> Vec<u32> {
+        done.iter().map(|(p, _)| p.pid).collect()
+    }
+
+    /// A source that has `running` already going, and reports whatever gets sent on its channel
+    struct Running {
+        running: Vec<ProcessInfo>,
+        events: async_channel::Receiver<ProcessInfo>
+    }
+
+    impl ProcessEventSource for Running {
+        fn start(&mut self) -> Result<async_channel::Receiver<ProcessInfo>, Box<dyn Error>> {
+            self.events.start()
+        }
+
+        fn stop(&mut self) {
+            self.events.stop();
+        }
+
+        fn running(&mut self) -> Result<Vec<ProcessInfo>, Box<dyn Error>> {
+            Ok(self.running.clone())
+        }
+    }
+
+    /// Has `processes` running, and remembers what it was asked to do, failing at it if `fail` is set
+    #[derive(Default)]
+    struct Tree {
+        processes: Vec<ProcessInfo>,
+        done: Vec<(ProcessInfo, Action)>,
+        fail: bool
+    }
+
+    impl ActionExecutor for Tree {
+        fn execute(&mut self, process: &ProcessInfo, action: &Action) -> Result<(), ProcessError> {
+            self.done.push((process.clone(), action.clone()));
+
+            match self.fail {
+                true => Err(ProcessError::SuspendFailed { process: process.name.clone(), pid: process.pid, errcode: 5 }),
+                false => Ok(())
+            }
+        }
+
+        fn processes(&mut self) -> Vec<ProcessInfo> {
+            self.processes.clone()
+        }
+    }
+
+    /// Anything executed fails the test
+    struct Untouchable(Vec<ProcessInfo>);
+
+    impl ActionExecutor for Untouchable {
+        fn execute(&mut self, process: &ProcessInfo, action: &Action) -> Result<(), ProcessError> {
+            panic!("{action} was executed on {}", process.name);
+        }
+
+        fn processes(&mut self) -> Vec<ProcessInfo> {
+            self.0.clone()
+        }
+    }
+
+    fn actions() -> Ruleset {
+        data(json!({
+            "version": config::VERSION,
+            "rules": [
+                { "match": { "name": "slow.exe" }, "action": { "priority": "idle" } },
+                { "match": { "name": "tree.exe" }, "action": "suspend", "tree": true },
+                { "match": { "name": "audit.exe" }, "audit": true, "tree": true },
+                { "match": { "name": "log.exe" }, "action": "log" }
+            ]
+        })).ruleset()
+    }
+
+    /// tree.exe (10) -> 11 -> 12, and 13 on its own
+    fn tree() -> Vec<ProcessInfo> {
+        vec![
+            process("tree.exe", 10),
+            ProcessInfo { parent_pid: 10, ..process("child.exe", 11) },
+            ProcessInfo { parent_pid: 11, ..process("grandchild.exe", 12) },
+            process("other.exe", 13)
+        ]
+        .into_iter()
+        .map(|v| ProcessInfo { start_time: Some(v.pid.into()), ..v })
+        .collect()
+    }
+
+    #[tokio::test]
+    async fn run_acts_on_events_until_shutdown() {
+        let (events_tx, events) = async_channel::unbounded();
+        let (_reloads_tx, reloads) = async_channel::unbounded();
+        let (shutdown_tx, mut shutdown) = tokio::sync::mpsc::channel(1);
+        let mut done = Vec::new();
+
+        let make_source = |_: &Data| -> Box<dyn ProcessEventSource> { Box::new(events.clone()) };
+
+        let watch = run(&make_source, &mut done, config(), "", reloads, false, &mut shutdown);
+        let send = async {
+            events_tx.send(process("bad.exe", 10)).await.unwrap();
+            events_tx.send(process("calc.exe", 11)).await.unwrap();
+            events_tx.send(ProcessInfo { parent_name: Some("good.exe".to_string()), ..process("bad.exe", 12) }).await.unwrap();
+            events_tx.send(process("BAD.EXE", 13)).await.unwrap();
+
+            // let it get through them before it's told to stop
+            while !events_tx.is_empty() {
+                tokio::task::yield_now().await;
+            }
+            shutdown_tx.send(()).await.unwrap();
+        };
+
+        let (result, ()) = tokio::join!(watch, send);
+
+        assert!(result.is_ok());
+        assert_eq!(pids(&done), vec![10, 13]);
+        assert!(done.iter().all(|(_, action)| *action == Action::default()));
+    }
+
+    #[tokio::test]
+    async fn run_restarts_a_source_that_stops() {
+        let (events_tx, events) = async_channel::unbounded();
+        let (_reloads_tx, reloads) = async_channel::unbounded();
+        let (_shutdown_tx, mut shutdown) = tokio::sync::mpsc::channel(1);
+        let mut done = Vec::new();
+
+        // the source never comes back, so it gets made once and then once for each restart
+        let made = Cell::new(0);
+        let make_source = |_: &Data| -> Box<dyn ProcessEventSource> {
+            made.set(made.get() + 1);
+            Box::new(events.clone())
+        };
+
+        events_tx.send(process("bad.exe", 10)).await.unwrap();
+        events_tx.close();
+
+        let result = run(&make_source, &mut done, config(), "", reloads, false, &mut shutdown).await;
+
+        let error = result.unwrap_err();
+        assert!(matches!(error.downcast_ref(), Some(SourceError::KeptStopping(MAX_RESTARTS))));
+        assert_eq!(made.get(), 1 + MAX_RESTARTS);
+        assert_eq!(pids(&done), vec![10]);
+    }
+
+    #[tokio::test]
+    async fn run_sweeps_what_was_already_running() {
+        let (_events_tx, events) = async_channel::unbounded();
+        let (_reloads_tx, reloads) = async_channel::unbounded();
+        let (shutdown_tx, mut shutdown) = tokio::sync::mpsc::channel(1);
+        let mut done = Vec::new();
+
+        let make_source = |_: &Data| -> Box<dyn ProcessEventSource> {
+            Box::new(Running {
+                running: vec![process("bad.exe", 10), process("calc.exe", 11)],
+                events: events.clone()
+            })
+        };
+
+        shutdown_tx.send(()).await.unwrap();
+        run(&make_source, &mut done, config(), "", reloads, false, &mut shutdown).await.unwrap();
+
+        assert_eq!(pids(&done), vec![10]);
+    }
+
+    #[test]
+    fn sweep_skips_ourselves() {
+        let (_events_tx, events) = async_channel::unbounded();
+        let us = std::process::id();
+
+        let make_source = |_: &Data| -> Box<dyn ProcessEventSource> {
+            Box::new(Running {
+                running: vec![process("bad.exe", us), process("bad.exe", us + 1), process("calc.exe", us + 2)],
+                events: events.clone()
+            })
+        };
+
+        let mut watching = Watching::start(config(), &make_source).unwrap();
+        let mut done = Vec::new();
+        sweep(&mut watching, &mut done, false).unwrap();
+
+        assert_eq!(pids(&done), vec![us + 1]);
+    }
+
+    #[test]
+    fn handle_goes_by_the_ruleset() {
+        let ruleset = config().ruleset();
+        let mut done = Vec::new();
+
+        handle(&mut done, &ruleset, &process("calc.exe", 1), false);
+        handle(&mut done, &ruleset, &ProcessInfo { parent_name: Some("GOOD.exe".to_string()), ..process("bad.exe", 2) }, false);
+        assert!(done.is_empty());
+
+        handle(&mut done, &ruleset, &process("bad.exe", 3), false);
+        assert_eq!(pids(&done), vec![3]);
+    }
+
+    #[test]
+    fn act_executes_the_rules_action() {
+        let ruleset = actions();
+        let mut done = Vec::new();
+
+        handle(&mut done, &ruleset, &process("slow.exe", 1), false);
+        handle(&mut done, &ruleset, &process("log.exe", 2), false);
+
+        assert_eq!(done, vec![
+            (process("slow.exe", 1), Action::Priority(PriorityClass::Idle)),
+            (process("log.exe", 2), Action::Log)
+        ]);
+    }
+
+    #[test]
+    fn audit_and_dry_run_never_execute() {
+        let ruleset = actions();
+        let mut executor = Untouchable(tree());
+
+        for name in ["slow.exe", "tree.exe", "log.exe"] {
+            handle(&mut executor, &ruleset, &process(name, 10), true);
+        }
+
+        handle(&mut executor, &ruleset, &process("audit.exe", 10), false);
+    }
+
+    #[test]
+    fn tree_goes_children_first() {
+        let ruleset = actions();
+        let mut executor = Tree { processes: tree(), ..Default::default() };
+
+        handle(&mut executor, &ruleset, &process("tree.exe", 10), false);
+
+        assert_eq!(pids(&executor.done), vec![12, 11, 10]);
+        assert!(executor.done.iter().all(|(_, action)| *action == Action::Suspend));
+    }
+
+    #[test]
+    fn without_tree_children_are_left_alone() {
+        let ruleset = actions();
+        let mut executor = Tree { processes: tree(), ..Default::default() };
+
+        handle(&mut executor, &ruleset, &process("slow.exe", 10), false);
+
+        assert_eq!(pids(&executor.done), vec![10]);
+    }
+
+    #[test]
+    fn failing_doesnt_stop_the_tree() {
+        let ruleset = actions();
+        let mut executor = Tree { processes: tree(), fail: true, ..Default::default() };
+
+        handle(&mut executor, &ruleset, &process("tree.exe", 10), false);
+
+        assert_eq!(pids(&executor.done), vec![12, 11, 10]);
+    }
+
+    /// `config` written out to a file of its own, which is removed when this is dropped
+    struct ConfigFile(String);
+
+    impl ConfigFile {
+        fn new(name: &str, config: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("process-killer-{}-{name}", std::process::id()));
+            std::fs::write(&path, config).unwrap();
+
+            Self(path.to_str().unwrap().to_string())
+        }
+    }
+
+    impl Drop for ConfigFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn reload_keeps_the_last_good_config() {
+        let (_events_tx, events) = async_channel::unbounded();
+        let made = Cell::new(0);
+        let make_source = |_: &Data| -> Box<dyn ProcessEventSource> {
+            made.set(made.get() + 1);
+            Box::new(events.clone())
+        };
+
+        for (name, text) in [
+            ("syntax.json", "{ \"version\": 2, "),
+            ("unknown.json", r#"{ "version": 2, "rules": [{ "match": { "nmae": "calc.exe" } }] }"#),
+            ("empty.json", r#"{ "version": 2, "rules": [{ "match": {} }] }"#)
+        ] {
+            let file = ConfigFile::new(name, text);
+            assert!(reload(&config(), &file.0, Reason::Changed, &make_source).is_none(), "{name}");
+        }
+
+        assert_eq!(made.get(), 0);
+    }
+
+    #[test]
+    fn reload_without_changes() {
+        let make_source = |_: &Data| -> Box<dyn ProcessEventSource> { panic!("nothing changed") };
+
+        let file = ConfigFile::new("same.json", &serde_json::to_string(&config()).unwrap());
+        assert!(reload(&config(), &file.0, Reason::Command, &make_source).is_none());
+    }
+
+    #[test]
+    fn reload_swaps_in_the_new_config() {
+        let (_events_tx, events) = async_channel::unbounded();
+        let make_source = |_: &Data| -> Box<dyn ProcessEventSource> { Box::new(events.clone()) };
+
+        let file = ConfigFile::new("new.json", r#"{ "version": 2, "rules": [{ "match": { "name": "calc.exe" } }] }"#);
+        let watching = reload(&config(), &file.0, Reason::Command, &make_source).unwrap();
+
+        assert!(matches!(watching.ruleset.evaluate(&process("calc.exe", 1)), Decision::Kill(_)));
+        assert!(matches!(watching.ruleset.evaluate(&process("bad.exe", 2)), Decision::NoMatch));
+    }
+
+    #[tokio::test]
+    async fn run_keeps_going_after_an_invalid_reload() {
+        let (events_tx, events) = async_channel::unbounded();
+        let (reloads_tx, reloads) = async_channel::unbounded();
+        let (shutdown_tx, mut shutdown) = tokio::sync::mpsc::channel(1);
+        let mut done = Vec::new();
+
+        let make_source = |_: &Data| -> Box<dyn ProcessEventSource> { Box::new(events.clone()) };
+        let file = ConfigFile::new("invalid.json", r#"{ "version": 2, "rules": [{ "match": { "name": "[calc" } }] }"#);
+
+        let watch = run(&make_source, &mut done, config(), &file.0, reloads, false, &mut shutdown);
+        let send = async {
+            reloads_tx.send(Reason::Changed).await.unwrap();
+            while !reloads_tx.is_empty() {
+                tokio::task::yield_now().await;
+            }
+
+            events_tx.send(process("bad.exe", 10)).await.unwrap();
+            while !events_tx.is_empty() {
+                tokio::task::yield_now().await;
+            }
+            shutdown_tx.send(()).await.unwrap();
+        };
+
+        let (result, ()) = tokio::join!(watch, send);
+
+        assert!(result.is_ok());
+        assert_eq!(pids(&done), vec![10]);
+    }
+}
@@ -0,0 +1,372 @@
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+#[cfg(any(windows, test))]
+use WMI_Query::wql::like_escape;
+
+
+#[derive(Error, Debug)]
+pub enum PatternError {
+    #[error("Invalid regex -> {pattern} -> {source}")]
+    InvalidRegex {
+        pattern: String,
+        source: regex::Error
+    },
+
+    #[error("Unclosed character class in glob -> {pattern}")]
+    UnclosedClass {
+        pattern: String
+    }
+}
+
+/// How a pattern is written in the config
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum PatternSpec {
+    /// An exact name, or a glob if it has any of `*?[` in it
+    Plain(String),
+
+    Glob {
+        glob: String
+    },
+
+    Regex {
+        regex: String
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Matcher {
+    /// Already lowercased
+    Exact(String),
+    Regex(Regex)
+}
+
+/// A compiled name pattern. Matching is always case insensitive,
+/// regexes can opt out with `(?-i)`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "PatternSpec", into = "PatternSpec")]
+pub struct Pattern {
+    spec: PatternSpec,
+    matcher: Matcher
+}
+
+impl Pattern {
+    pub fn new(spec: PatternSpec) -> Result<Self, PatternError> {
+        let matcher = match &spec {
+            PatternSpec::Plain(v) if !is_glob(v) => Matcher::Exact(v.to_lowercase()),
+            PatternSpec::Plain(v) | PatternSpec::Glob { glob: v } => Matcher::Regex(compile(v, &glob_to_regex(v)?)?),
+            PatternSpec::Regex { regex } => Matcher::Regex(compile(regex, regex)?)
+        };
+
+        Ok(Self {
+            spec,
+            matcher
+        })
+    }
+
+    pub fn matches(&self, value: &str) -> bool {
+        match &self.matcher {
+            Matcher::Exact(v) => value.to_lowercase() == *v,
+            Matcher::Regex(v) => v.is_match(value)
+        }
+    }
+
+    /// Whether this matches everything `other` does, as far as can be told without trying every name
+    pub fn covers(&self, other: &Pattern) -> bool {
+        if self.spec == other.spec {
+            return true;
+        }
+
+        // an exact name is only that one name, in any case, so trying it is enough.
+        // That doesn't hold for a regex that stops ignoring case.
+        match (&other.matcher, &self.spec) {
+            (_, PatternSpec::Regex { regex }) if regex.contains("(?-i") => false,
+            (Matcher::Exact(v), _) => self.matches(v),
+            (Matcher::Regex(_), _) => false
+        }
+    }
+
+    /// The same match as a WQL `LIKE` pattern, which is case insensitive too, so WMI can do the matching.
+    /// Regexes can't be written as one.
+    #[cfg(any(windows, test))]
+    pub fn to_like(&self) -> Option<String> {
+        match &self.spec {
+            PatternSpec::Plain(v) if !is_glob(v) => Some(like_escape(v)),
+            PatternSpec::Plain(v) | PatternSpec::Glob { glob: v } => glob_to_like(v),
+            PatternSpec::Regex { .. } => None
+        }
+    }
+}
+
+impl TryFrom<PatternSpec> for Pattern {
+    type Error = PatternError;
+
+    fn try_from(spec: PatternSpec) -> Result<Self, Self::Error> {
+        Self::new(spec)
+    }
+}
+
+impl From<Pattern> for PatternSpec {
+    fn from(pattern: Pattern) -> Self {
+        pattern.spec
+    }
+}
+
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.spec {
+            PatternSpec::Plain(v) => write!(f, "{v}"),
+            PatternSpec::Glob { glob } => write!(f, "glob:{glob}"),
+            PatternSpec::Regex { regex } => write!(f, "regex:{regex}")
+        }
+    }
+}
+
+fn is_glob(value: &str) -> bool {
+    value.contains(['*', '?', '['])
+}
+
+fn compile(pattern: &str, regex: &str) -> Result<Regex, PatternError> {
+    RegexBuilder::new(regex)
+        .case_insensitive(true)
+        .build()
+        .map_err(|source| PatternError::InvalidRegex {
+            pattern: pattern.to_string(),
+            source
+        })
+}
+
+/// `*` is anything, `?` is any one character, and `[...]` / `[!...]` is a character class.
+/// The glob has to match the whole value.
+fn glob_to_regex(glob: &str) -> Result<String, PatternError> {
+    let mut regex = String::from("^");
+    let mut chars = glob.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+
+            '[' => {
+                regex.push('[');
+
+                if chars.next_if_eq(&'!').is_some() {
+                    regex.push('^');
+                }
+
+                // a ] right at the start is part of the class
+                if chars.next_if_eq(&']').is_some() {
+                    regex.push_str("\\]");
+                }
+
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    match c {
+                        ']' => {
+                            closed = true;
+                            break;
+                        }
+
+                        // keep `-` for ranges, but nothing else is special in a glob class
+                        '\\' | '[' | '^' | '&' | '~' => {
+                            regex.push('\\');
+                            regex.push(c);
+                        }
+
+                        c => regex.push(c)
+                    }
+                }
+
+                if !closed {
+                    return Err(PatternError::UnclosedClass {
+                        pattern: glob.to_string()
+                    });
+                }
+
+                regex.push(']');
+            }
+
+            c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4])))
+        }
+    }
+
+    regex.push('$');
+
+    Ok(regex)
+}
+
+/// Like `glob_to_regex`, but for a WQL `LIKE`. Classes with anything but plain characters and ranges in them
+/// might not mean the same thing there, so those globs have none.
+#[cfg(any(windows, test))]
+fn glob_to_like(glob: &str) -> Option<String> {
+    let mut like = String::new();
+    let mut chars = glob.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => like.push('%'),
+            '?' => like.push('_'),
+
+            '[' => {
+                let class: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                let (negated, members) = match class.strip_prefix('!') {
+                    Some(v) => (true, v),
+                    None => (false, class.as_str())
+                };
+
+                // `[[]` is how `LIKE` writes a plain `[` too
+                if !negated && members == "[" {
+                    like.push_str("[[]");
+                    continue;
+                }
+
+                if members.is_empty() || members.contains(['[', '^', '\\']) {
+                    return None;
+                }
+
+                like.push('[');
+                if negated {
+                    like.push('^');
+                }
+                like.push_str(members);
+                like.push(']');
+            }
+
+            c => like.push_str(&like_escape(c.encode_utf8(&mut [0; 4])))
+        }
+    }
+
+    Some(like)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(v: &str) -> Pattern {
+        Pattern::new(PatternSpec::Plain(v.to_string())).unwrap()
+    }
+
+    fn glob(v: &str) -> Pattern {
+        Pattern::new(PatternSpec::Glob { glob: v.to_string() }).unwrap()
+    }
+
+    fn regex(v: &str) -> Pattern {
+        Pattern::new(PatternSpec::Regex { regex: v.to_string() }).unwrap()
+    }
+
+    #[test]
+    fn globs() {
+        assert_eq!(glob_to_regex("note*.ex?").unwrap(), r"^note.*\.ex.$");
+        assert_eq!(glob_to_regex("a[b-d]").unwrap(), "^a[b-d]$");
+        assert_eq!(glob_to_regex("a[!bc]").unwrap(), "^a[^bc]$");
+    }
+
+    #[test]
+    fn leading_bracket_is_in_the_class() {
+        assert_eq!(glob_to_regex("[]x]").unwrap(), r"^[\]x]$");
+        assert_eq!(glob_to_regex("[!]x]").unwrap(), r"^[^\]x]$");
+        assert!(glob("[]x]").matches("]"));
+        assert!(!glob("[!]x]").matches("]"));
+        assert!(glob("[!]x]").matches("y"));
+    }
+
+    #[test]
+    fn globs_escape_the_rest() {
+        assert_eq!(glob_to_regex("a.b+(c)$").unwrap(), r"^a\.b\+\(c\)\$$");
+        assert_eq!(glob_to_regex(r"[\^&~[]").unwrap(), r"^[\\\^\&\~\[]$");
+        assert!(glob("a.b").matches("A.B"));
+        assert!(!glob("a.b").matches("axb"));
+        assert!(glob("[^]").matches("^"));
+        assert!(!glob("[^]").matches("a"));
+    }
+
+    #[test]
+    fn unclosed_class() {
+        assert!(matches!(glob_to_regex("ab[cd"), Err(PatternError::UnclosedClass { .. })));
+        assert!(matches!(glob_to_regex("[]"), Err(PatternError::UnclosedClass { .. })));
+        assert!(matches!(
+            Pattern::new(PatternSpec::Plain("ab[cd".to_string())),
+            Err(PatternError::UnclosedClass { .. })
+        ));
+    }
+
+    #[test]
+    fn plain_names_are_exact() {
+        assert!(plain("Notepad.exe").matches("NOTEPAD.EXE"));
+        assert!(!plain("notepad.exe").matches("notepadXexe"));
+        assert!(!plain("notepad.exe").matches("notepad.exe.bak"));
+    }
+
+    #[test]
+    fn plain_names_with_glob_characters_are_globs() {
+        assert!(plain("note*").matches("NotePad.exe"));
+        assert!(plain("[!a]*").matches("bcd"));
+        assert!(!plain("[!a]*").matches("abc"));
+    }
+
+    #[test]
+    fn case_folding() {
+        assert!(glob("[a-c]x").matches("BX"));
+        assert!(!glob("[!a]*").matches("ABC"));
+        assert!(regex("^note").matches("NOTEPAD"));
+        assert!(regex("(?-i)^Note").matches("Notepad"));
+        assert!(!regex("(?-i)^Note").matches("notepad"));
+    }
+
+    #[test]
+    fn invalid_regex() {
+        assert!(matches!(
+            Pattern::new(PatternSpec::Regex { regex: "(".to_string() }),
+            Err(PatternError::InvalidRegex { .. })
+        ));
+    }
+
+    #[test]
+    fn covers() {
+        assert!(glob("note*").covers(&glob("note*")));
+        assert!(glob("note*").covers(&plain("NotePad.exe")));
+        assert!(regex("^note").covers(&plain("notepad.exe")));
+        assert!(plain("notepad.exe").covers(&plain("NOTEPAD.EXE")));
+        assert!(!plain("notepad.exe").covers(&glob("notepad.ex?")));
+        assert!(!glob("note*").covers(&glob("notepad*")));
+        assert!(!glob("note*").covers(&plain("calc.exe")));
+        // it would miss the other cases of the name
+        assert!(!regex("(?-i)^note").covers(&plain("notepad.exe")));
+    }
+
+    #[test]
+    fn serde() {
+        let spec: PatternSpec = serde_json::from_str(r#""a*""#).unwrap();
+        assert_eq!(spec, PatternSpec::Plain("a*".to_string()));
+
+        let spec: PatternSpec = serde_json::from_str(r#"{"glob": "a"}"#).unwrap();
+        assert_eq!(spec, PatternSpec::Glob { glob: "a".to_string() });
+
+        assert!(serde_json::from_str::<PatternSpec>(r#"{"glob": "a", "regex": "b"}"#).is_err());
+        assert!(serde_json::from_str::<Pattern>(r#"{"regex": "("}"#).is_err());
+    }
+
+    #[test]
+    fn likes() {
+        assert_eq!(plain("50%_x.exe").to_like().unwrap(), "50[%][_]x.exe");
+        assert_eq!(glob("[[]x]*").to_like().unwrap(), "[[]x]%");
+        assert_eq!(glob("note*.ex?").to_like().unwrap(), "note%.ex_");
+        assert_eq!(glob("100%*").to_like().unwrap(), "100[%]%");
+        assert_eq!(glob("a_[b-d]*").to_like().unwrap(), "a[_][b-d]%");
+        assert_eq!(glob("a[!bc]").to_like().unwrap(), "a[^bc]");
+        assert_eq!(regex("^a").to_like(), None);
+    }
+
+    #[test]
+    fn likes_without_classes_they_cant_write() {
+        assert_eq!(glob_to_like("[]x]"), None);
+        assert_eq!(glob_to_like("[!]x]"), None);
+        assert_eq!(glob_to_like("[a^]"), None);
+        assert_eq!(glob_to_like(r"[a\]"), None);
+        assert_eq!(glob_to_like("[[a]"), None);
+        assert_eq!(glob_to_like("[![]"), None);
+        assert_eq!(glob("[^]").to_like(), None);
+    }
+}
@@ -0,0 +1,202 @@
+use crate::{
+    rules::{RuleEntry, Ruleset},
+    source::ProcessInfo,
+    threshold::{ProcessKey, Tracker, Usage}
+};
+
+use std::{
+    collections::HashSet, error::Error,
+    sync::mpsc::{self, RecvTimeoutError}, thread::JoinHandle, time::{Duration, Instant}
+};
+
+use async_channel::{unbounded, Receiver};
+use thiserror::Error;
+
+
+/// How often usage is sampled for threshold rules
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Error, Debug)]
+pub enum MonitorError {
+    #[error("Failed to start usage monitor -> {0}")]
+    StartFailed(String)
+}
+
+/// A process that has stayed over a threshold rule's limits, and that rule
+pub type Breach = (ProcessInfo, RuleEntry);
+
+/// One process' usage at the time it was sampled
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub key: ProcessKey,
+    pub process: ProcessInfo,
+    pub usage: Usage
+}
+
+/// Something that can read how much every running process is using
+pub trait UsageSampler {
+    fn sample(&mut self) -> Result<Vec<Sample>, Box<dyn Error>>;
+}
+
+/// Replays the samples it was given, one list per call
+#[cfg(test)]
+impl UsageSampler for std::vec::IntoIter<Vec<Sample>> {
+    fn sample(&mut self) -> Result<Vec<Sample>, Box<dyn Error>> {
+        Ok(self.next().unwrap_or_default())
+    }
+}
+
+/// Samples usage on its own thread, and reports each process that stays over a threshold rule's limits
+pub struct Monitor {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>
+}
+
+impl Monitor {
+    /// `make_sampler` runs on the monitor's thread, since some samplers can't be moved between threads.
+    /// Every process that has been over a rule in `ruleset.thresholds` for long enough is sent along with that rule.
+    pub fn start<S, F>(make_sampler: F, ruleset: Ruleset, interval: Duration) -> Result<(Self, Receiver<Breach>), Box<dyn Error>>
+    where
+        S: UsageSampler,
+        F: FnOnce() -> Result<S, Box<dyn Error>> + Send + 'static
+    {
+        let (tx, rx) = unbounded();
+        let (stop_tx, stop_rx) = mpsc::channel::<()>();
+        let (ready_tx, ready_rx) = mpsc::channel();
+
+        let thread = std::thread::spawn(move || {
+            let mut sampler = match make_sampler() {
+                Ok(v) => v,
+                Err(e) => {
+                    let _ = ready_tx.send(Err(e.to_string()));
+                    return;
+                }
+            };
+
+            let _ = ready_tx.send(Ok(()));
+
+            let cpus = std::thread::available_parallelism().map(|v| v.get() as u32).unwrap_or(1);
+            let mut tracker = Tracker::new(cpus);
+
+            loop {
+                match sampler.sample() {
+                    Ok(samples) => {
+                        let now = Instant::now();
+
+                        for sample in &samples {
+                            for (i, entry) in ruleset.watched(&sample.process) {
+                                let threshold = match &entry.rule.threshold {
+                                    Some(v) => v,
+                                    None => continue
+                                };
+
+                                if tracker.update(now, i, threshold, sample.key, sample.usage)
+                                    && tx.try_send((sample.process.clone(), entry.clone())).is_err()
+                                {
+                                    return;
+                                }
+                            }
+                        }
+
+                        let alive: HashSet<ProcessKey> = samples.iter().map(|v| v.key).collect();
+                        tracker.retain(|key| alive.contains(key));
+                    }
+
+                    Err(e) => println!("Warning: Failed to sample usage: {e}")
+                }
+
+                // anything else means we were told to stop, or the monitor was dropped
+                if !matches!(stop_rx.recv_timeout(interval), Err(RecvTimeoutError::Timeout)) {
+                    break;
+                }
+            }
+
+            tx.close();
+        });
+
+        match ready_rx.recv() {
+            Ok(Ok(())) => (),
+            Ok(Err(e)) => {
+                let _ = thread.join();
+                return Err(Box::new(MonitorError::StartFailed(e)));
+            }
+            Err(e) => return Err(Box::new(MonitorError::StartFailed(e.to_string())))
+        }
+
+        let monitor = Self {
+            stop: Some(stop_tx),
+            thread: Some(thread)
+        };
+
+        Ok((monitor, rx))
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rules::Rule, threshold::Threshold};
+
+    fn ruleset(rules: serde_json::Value) -> Ruleset {
+        let rules: Vec<Rule> = serde_json::from_value(rules).unwrap();
+        let (thresholds, kill) = rules.into_iter()
+            .enumerate()
+            .map(|(i, r)| RuleEntry::new("rules", i, r))
+            .partition(|e| e.rule.threshold.is_some());
+
+        Ruleset { thresholds, kill, ..Default::default() }
+    }
+
+    fn sample(name: &str, pid: u32, memory: u64) -> Sample {
+        Sample {
+            key: (pid, 0),
+            process: ProcessInfo { name: name.to_string(), pid, ..Default::default() },
+            usage: Usage { memory, cpu_time: Duration::ZERO }
+        }
+    }
+
+    #[test]
+    fn reports_breaches() {
+        let ruleset = ruleset(serde_json::json!([
+            { "match": { "name": "big.exe" }, "threshold": { "memory": 100 } },
+            { "match": { "name": "*" } }
+        ]));
+        assert_eq!(ruleset.thresholds[0].rule.threshold, Some(Threshold { memory: Some(100), ..Default::default() }));
+
+        let samples = vec![
+            vec![sample("big.exe", 1, 50), sample("small.exe", 2, 500)],
+            vec![sample("big.exe", 1, 200), sample("big.exe", 3, 50)]
+        ];
+
+        let (mut monitor, breaches) = Monitor::start(|| Ok(samples.into_iter()), ruleset, Duration::from_millis(1)).unwrap();
+
+        let (process, rule) = breaches.recv_blocking().unwrap();
+        assert_eq!(process.pid, 1);
+        assert_eq!(rule.path, "rules[0]");
+
+        monitor.stop();
+        assert!(breaches.recv_blocking().is_err());
+    }
+
+    #[test]
+    fn start_fails_with_the_sampler() {
+        let result = Monitor::start(|| Err::<std::vec::IntoIter<Vec<Sample>>, _>("no".into()), Ruleset::default(), SAMPLE_INTERVAL);
+        assert!(matches!(result, Err(e) if e.to_string().contains("no")));
+    }
+}
@@ -0,0 +1,114 @@
+use std::{fmt, fs, io::BufRead, time::{Duration, SystemTime}};
+
+use async_channel::{unbounded, Receiver, Sender};
+
+
+/// How often the config file is checked for changes
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Why the config is being read again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    /// The file was saved
+    Changed,
+    /// SIGHUP
+    #[cfg(unix)]
+    Hangup,
+    /// `reload` was typed into the console
+    Command
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::Changed => write!(f, "it changed"),
+            #[cfg(unix)]
+            Reason::Hangup => write!(f, "of SIGHUP"),
+            Reason::Command => write!(f, "it was asked to")
+        }
+    }
+}
+
+/// Sends whenever the config at `path` should be read again: when the file changes, on SIGHUP,
+/// or when `reload` is typed into the console. Has to be called from inside the runtime.
+pub fn triggers(path: &str) -> Receiver<Reason> {
+    let (tx, rx) = unbounded();
+
+    watch_file(path.to_string(), tx.clone());
+    read_commands(tx.clone());
+
+    #[cfg(unix)]
+    tokio::spawn(forward_hangups(tx));
+
+    rx
+}
+
+/// When it was last written and how big it is, which is enough to tell it was saved
+fn stamp(path: &str) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Polling the metadata works the same everywhere, and a second is quick enough for someone editing by hand
+fn watch_file(path: String, tx: Sender<Reason>) {
+    std::thread::spawn(move || {
+        let mut last = stamp(&path);
+
+        loop {
+            std::thread::sleep(CHECK_INTERVAL);
+
+            let current = stamp(&path);
+            // a missing file is left alone, it's likely in the middle of being replaced
+            if current.is_none() || current == last {
+                continue;
+            }
+
+            last = current;
+            if tx.try_send(Reason::Changed).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+fn read_commands(tx: Sender<Reason>) {
+    std::thread::spawn(move || {
+        // ends on its own when there's no console to read from
+        for line in std::io::stdin().lock().lines() {
+            let line = match line {
+                Ok(v) => v,
+                Err(_) => break
+            };
+
+            match line.trim() {
+                "" => (),
+                v if v.eq_ignore_ascii_case("reload") => {
+                    if tx.try_send(Reason::Command).is_err() {
+                        break;
+                    }
+                }
+                v => println!("Warning: Unknown command {v}, the only one is `reload`")
+            }
+        }
+    });
+}
+
+#[cfg(unix)]
+async fn forward_hangups(tx: Sender<Reason>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(v) => v,
+        Err(e) => {
+            println!("Warning: Can't reload on SIGHUP: {e}");
+            return;
+        }
+    };
+
+    while hangups.recv().await.is_some() {
+        if tx.send(Reason::Hangup).await.is_err() {
+            break;
+        }
+    }
+}
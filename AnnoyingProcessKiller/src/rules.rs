@@ -0,0 +1,479 @@
+use crate::{actions::{self, Action}, matcher::Pattern, source::ProcessInfo, threshold::Threshold};
+
+use serde::{Deserialize, Serialize};
+
+
+/// A set of predicates on a process.
+/// Every predicate that is set has to match, as do all of `all`, and at least one of `any`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Condition {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<Pattern>,
+
+    /// Full path of the executable
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<Pattern>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cmdline: Option<Pattern>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_name: Option<Pattern>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<u32>,
+
+    /// Matches either `DOMAIN\user` or just `user` on Windows
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<Pattern>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub all: Vec<Condition>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub any: Vec<Condition>
+}
+
+impl Condition {
+    pub fn matches(&self, process: &ProcessInfo) -> bool {
+        // anything we couldn't find out about the process can't match
+        let check = |pattern: &Option<Pattern>, value: Option<&str>| match pattern {
+            Some(p) => value.is_some_and(|v| p.matches(v)),
+            None => true
+        };
+
+        let user = |p: &Pattern| {
+            process.user.as_deref().is_some_and(|v| {
+                p.matches(v) || v.rsplit_once('\\').is_some_and(|(_, name)| p.matches(name))
+            })
+        };
+
+        check(&self.name, Some(&process.name))
+            && check(&self.path, process.executable_path.as_deref())
+            && check(&self.cmdline, process.command_line.as_deref())
+            && check(&self.parent_name, process.parent_name.as_deref())
+            && self.session.is_none_or(|v| process.session_id == Some(v))
+            && self.user.as_ref().is_none_or(user)
+            && self.all.iter().all(|c| c.matches(process))
+            && (self.any.is_empty() || self.any.iter().any(|c| c.matches(process)))
+    }
+
+    /// An empty condition would match every process, which is never what anyone wants
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none()
+            && self.path.is_none()
+            && self.cmdline.is_none()
+            && self.parent_name.is_none()
+            && self.session.is_none()
+            && self.user.is_none()
+            && self.all.is_empty()
+            && self.any.is_empty()
+    }
+
+    /// Whether this or anything nested in it is empty
+    pub fn has_empty(&self) -> bool {
+        self.is_empty() || self.all.iter().chain(&self.any).any(Condition::has_empty)
+    }
+
+    /// Whether this matches every process `other` does. Only simple cases are caught,
+    /// so `false` doesn't mean there's a process that only `other` matches.
+    pub fn covers(&self, other: &Condition) -> bool {
+        let field = |mine: &Option<Pattern>, theirs: &Option<Pattern>| match (mine, theirs) {
+            (None, _) => true,
+            (Some(a), Some(b)) => a.covers(b),
+            (Some(_), None) => false
+        };
+
+        // nested conditions are too much to compare, unless they're the same
+        let nested = (self.all.is_empty() && self.any.is_empty())
+            || (serde_json::to_value((&self.all, &self.any)).ok() == serde_json::to_value((&other.all, &other.any)).ok());
+
+        nested
+            && field(&self.name, &other.name)
+            && field(&self.path, &other.path)
+            && field(&self.cmdline, &other.cmdline)
+            && field(&self.parent_name, &other.parent_name)
+            && field(&self.user, &other.user)
+            && self.session.is_none_or(|v| other.session == Some(v))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// Shows up in the log when this rule is the one that decided
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    #[serde(rename = "match")]
+    pub condition: Condition,
+
+    /// Only used by kill rules
+    #[serde(default, deserialize_with = "actions::deserialize_action")]
+    pub action: Action,
+
+    /// Only log what this rule would do, like `--dry-run` does for every rule
+    #[serde(default)]
+    pub audit: bool,
+
+    /// Do the same to every descendant of the process, children first
+    #[serde(default)]
+    pub tree: bool,
+
+    /// Only act once the process has used too much for a while, instead of when it starts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<Threshold>
+}
+
+impl Rule {
+    pub fn matches(&self, process: &ProcessInfo) -> bool {
+        self.condition.matches(process)
+    }
+}
+
+/// A rule along with where it came from in the config
+#[derive(Debug, Clone)]
+pub struct RuleEntry {
+    /// e.g. `allow[0]`
+    pub path: String,
+    /// e.g. `allow[0]` or `rules[2] (some id)`
+    pub label: String,
+    pub rule: Rule
+}
+
+impl RuleEntry {
+    pub fn new(list: &str, index: usize, rule: Rule) -> Self {
+        let path = format!("{list}[{index}]");
+        let label = match &rule.id {
+            Some(id) => format!("{path} ({id})"),
+            None => path.clone()
+        };
+
+        Self {
+            path,
+            label,
+            rule
+        }
+    }
+}
+
+impl std::fmt::Display for RuleEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.label)
+    }
+}
+
+/// What to do with a process, and which rule said so
+#[derive(Debug, Clone, Copy)]
+pub enum Decision<'a> {
+    /// An allow rule matched, which wins over any kill rule
+    Allow(&'a RuleEntry),
+    Kill(&'a RuleEntry),
+    /// Nothing matched, so it's left alone
+    NoMatch
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Ruleset {
+    pub allow: Vec<RuleEntry>,
+    pub kill: Vec<RuleEntry>,
+    /// Kill rules with a threshold, which are checked against samples instead of when a process starts
+    pub thresholds: Vec<RuleEntry>
+}
+
+impl Ruleset {
+    /// Kill rules with a threshold are kept apart, since they aren't checked when a process starts
+    pub fn new(allow: Vec<RuleEntry>, rules: Vec<RuleEntry>) -> Self {
+        let (thresholds, kill) = rules.into_iter().partition(|e| e.rule.threshold.is_some());
+
+        Self {
+            allow,
+            kill,
+            thresholds
+        }
+    }
+
+    /// Allow rules are checked first, then kill rules, each in order. The first match wins.
+    pub fn evaluate(&self, process: &ProcessInfo) -> Decision<'_> {
+        if let Some(entry) = self.allow.iter().find(|e| e.rule.matches(process)) {
+            return Decision::Allow(entry);
+        }
+
+        if let Some(entry) = self.kill.iter().find(|e| e.rule.matches(process)) {
+            return Decision::Kill(entry);
+        }
+
+        Decision::NoMatch
+    }
+
+    /// The name pattern of every kill rule, or nothing if any of them doesn't look at the name.
+    /// A process that none of these match can't be killed when it starts.
+    #[cfg(windows)]
+    pub fn kill_names(&self) -> Option<Vec<&Pattern>> {
+        self.kill.iter().map(|e| e.rule.condition.name.as_ref()).collect()
+    }
+
+    /// The threshold rules `process` has to be watched for, along with their index in `thresholds`.
+    /// Allow rules win over these too.
+    pub fn watched<'a>(&'a self, process: &'a ProcessInfo) -> impl Iterator<Item = (usize, &'a RuleEntry)> + 'a {
+        let allowed = self.allow.iter().any(|e| e.rule.matches(process));
+
+        self.thresholds
+            .iter()
+            .enumerate()
+            .filter(move |(_, e)| !allowed && e.rule.matches(process))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    fn condition(value: serde_json::Value) -> Condition {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn process(name: &str) -> ProcessInfo {
+        ProcessInfo {
+            name: name.to_string(),
+            pid: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn every_field_has_to_match() {
+        let c = condition(json!({ "name": "updater.exe", "cmdline": "*--silent*" }));
+
+        let silent = ProcessInfo { command_line: Some("updater.exe --silent".to_string()), ..process("Updater.exe") };
+        assert!(c.matches(&silent));
+        assert!(!c.matches(&ProcessInfo { command_line: Some("updater.exe".to_string()), ..process("updater.exe") }));
+        assert!(!c.matches(&ProcessInfo { name: "other.exe".to_string(), ..silent }));
+    }
+
+    #[test]
+    fn unknown_fields_never_match() {
+        // no path, parent, session or user could be found out
+        for c in [
+            json!({ "path": "*" }),
+            json!({ "parent_name": "*" }),
+            json!({ "session": 0 }),
+            json!({ "user": "*" })
+        ] {
+            assert!(!condition(c.clone()).matches(&process("a.exe")), "{c}");
+        }
+    }
+
+    #[test]
+    fn all_and_any() {
+        let c = condition(json!({
+            "name": "*.exe",
+            "all": [{ "parent_name": "launcher.exe" }, { "path": "C:\\Games\\*" }],
+            "any": [{ "session": 1 }, { "user": "bob" }, { "cmdline": "*--bob*" }]
+        }));
+
+        let game = ProcessInfo {
+            parent_name: Some("launcher.exe".to_string()),
+            executable_path: Some("C:\\Games\\game.exe".to_string()),
+            ..process("game.exe")
+        };
+
+        // nothing in any
+        assert!(!c.matches(&game));
+
+        for matching in [
+            ProcessInfo { session_id: Some(1), ..game.clone() },
+            ProcessInfo { user: Some("bob".to_string()), ..game.clone() },
+            ProcessInfo { command_line: Some("game.exe --bob".to_string()), ..game.clone() }
+        ] {
+            assert!(c.matches(&matching), "{matching:?}");
+
+            // missing one of all
+            assert!(!c.matches(&ProcessInfo { parent_name: Some("explorer.exe".to_string()), ..matching.clone() }));
+            assert!(!c.matches(&ProcessInfo { executable_path: None, ..matching }));
+        }
+    }
+
+    #[test]
+    fn nested_any_in_all() {
+        let c = condition(json!({ "all": [{ "any": [{ "name": "a.exe" }, { "name": "b.exe" }] }, { "session": 0 }] }));
+
+        assert!(c.matches(&ProcessInfo { session_id: Some(0), ..process("b.exe") }));
+        assert!(!c.matches(&ProcessInfo { session_id: Some(0), ..process("c.exe") }));
+        assert!(!c.matches(&ProcessInfo { session_id: Some(1), ..process("a.exe") }));
+    }
+
+    #[test]
+    fn sessions() {
+        let c = condition(json!({ "name": "a.exe", "session": 0 }));
+
+        assert!(c.matches(&ProcessInfo { session_id: Some(0), ..process("a.exe") }));
+        assert!(!c.matches(&ProcessInfo { session_id: Some(1), ..process("a.exe") }));
+        assert!(!c.matches(&process("a.exe")));
+    }
+
+    #[test]
+    fn users() {
+        let user = |name: &str| ProcessInfo { user: Some(name.to_string()), ..process("a.exe") };
+
+        let bob = condition(json!({ "user": "bob" }));
+        assert!(bob.matches(&user("bob")));
+        assert!(bob.matches(&user("DESKTOP-1\\Bob")));
+        assert!(!bob.matches(&user("DESKTOP-1\\bobby")));
+        assert!(!bob.matches(&user("bob\\alice")));
+
+        let domain = condition(json!({ "user": "CORP\\*" }));
+        assert!(domain.matches(&user("corp\\alice")));
+        assert!(!domain.matches(&user("alice")));
+        assert!(!domain.matches(&user("HOME\\alice")));
+
+        // only the part after the last separator is the name
+        assert!(condition(json!({ "user": "svc" })).matches(&user("A\\B\\svc")));
+    }
+
+    #[test]
+    fn empty() {
+        assert!(Condition::default().is_empty());
+        assert!(!condition(json!({ "session": 0 })).is_empty());
+
+        assert!(condition(json!({ "name": "a.exe", "any": [{}] })).has_empty());
+        assert!(condition(json!({ "all": [{ "any": [{ "name": "a.exe" }, {}] }] })).has_empty());
+        assert!(!condition(json!({ "all": [{ "any": [{ "name": "a.exe" }] }] })).has_empty());
+    }
+
+    fn ruleset(config: serde_json::Value) -> Ruleset {
+        let entries = |list: &str| -> Vec<RuleEntry> {
+            let rules: Vec<Rule> = serde_json::from_value(config.get(list).cloned().unwrap_or(json!([]))).unwrap();
+            rules.into_iter().enumerate().map(|(i, r)| RuleEntry::new(list, i, r)).collect()
+        };
+
+        Ruleset::new(entries("allow"), entries("rules"))
+    }
+
+    /// The path of the rule that decided, with `allow` or `kill` in front
+    fn decided(ruleset: &Ruleset, process: &ProcessInfo) -> String {
+        match ruleset.evaluate(process) {
+            Decision::Allow(e) => format!("allow {}", e.path),
+            Decision::Kill(e) => format!("kill {}", e.path),
+            Decision::NoMatch => "none".to_string()
+        }
+    }
+
+    #[test]
+    fn allow_wins_over_kill() {
+        let rules = ruleset(json!({
+            "rules": [{ "match": { "name": "*.exe" } }],
+            "allow": [{ "match": { "name": "explorer.exe" } }, { "match": { "parent_name": "explorer.exe" } }]
+        }));
+
+        assert_eq!(decided(&rules, &process("calc.exe")), "kill rules[0]");
+        assert_eq!(decided(&rules, &process("Explorer.EXE")), "allow allow[0]");
+        assert_eq!(decided(&rules, &ProcessInfo { parent_name: Some("explorer.exe".to_string()), ..process("calc.exe") }), "allow allow[1]");
+        assert_eq!(decided(&rules, &process("script.ps1")), "none");
+    }
+
+    #[test]
+    fn first_match_wins() {
+        let rules = ruleset(json!({
+            "rules": [
+                { "id": "first", "match": { "name": "up*.exe" }, "action": "log" },
+                { "match": { "name": "updater.exe" }, "action": "suspend" },
+                { "match": { "name": "*.exe" } }
+            ],
+            "allow": [{ "match": { "name": "a.exe" } }, { "match": { "name": "*" } }]
+        }));
+
+        match rules.evaluate(&process("updater.exe")) {
+            Decision::Allow(e) => assert_eq!(e.label, "allow[1]"),
+            d => panic!("{d:?}")
+        }
+
+        let rules = Ruleset { allow: Vec::new(), ..rules };
+        match rules.evaluate(&process("updater.exe")) {
+            Decision::Kill(e) => {
+                assert_eq!(e.label, "rules[0] (first)");
+                assert_eq!(e.rule.action, Action::Log);
+            }
+            d => panic!("{d:?}")
+        }
+        assert_eq!(decided(&rules, &process("calc.exe")), "kill rules[2]");
+    }
+
+    #[test]
+    fn thresholds_are_kept_apart() {
+        let rules = ruleset(json!({
+            "rules": [
+                { "match": { "name": "a.exe" } },
+                { "match": { "name": "big.exe" }, "threshold": { "memory": "1GB" } },
+                { "match": { "name": "*.exe" }, "threshold": { "cpu": 90 } }
+            ]
+        }));
+
+        let paths = |list: &[RuleEntry]| list.iter().map(|e| e.path.clone()).collect::<Vec<_>>();
+        assert_eq!(paths(&rules.kill), ["rules[0]"]);
+        assert_eq!(paths(&rules.thresholds), ["rules[1]", "rules[2]"]);
+
+        // threshold rules aren't decided on when a process starts
+        assert_eq!(decided(&rules, &process("big.exe")), "none");
+    }
+
+    #[test]
+    fn watched() {
+        let rules = ruleset(json!({
+            "rules": [
+                { "match": { "name": "a.exe" } },
+                { "match": { "name": "big.exe" }, "threshold": { "memory": "1GB" } },
+                { "match": { "name": "*.exe" }, "threshold": { "cpu": 90 } }
+            ],
+            "allow": [{ "match": { "user": "admin" } }]
+        }));
+
+        let watched = |process: &ProcessInfo| rules.watched(process).map(|(i, e)| (i, e.path.clone())).collect::<Vec<_>>();
+
+        assert_eq!(watched(&process("big.exe")), [(0, "rules[1]".to_string()), (1, "rules[2]".to_string())]);
+        assert_eq!(watched(&process("a.exe")), [(1, "rules[2]".to_string())]);
+        assert!(watched(&process("a.com")).is_empty());
+        assert!(watched(&ProcessInfo { user: Some("admin".to_string()), ..process("big.exe") }).is_empty());
+    }
+
+    #[test]
+    fn covers_exact_names() {
+        let covers = |a: serde_json::Value, b: serde_json::Value| condition(a).covers(&condition(b));
+
+        assert!(covers(json!({ "name": "a.exe" }), json!({ "name": "A.EXE" })));
+        assert!(!covers(json!({ "name": "a.exe" }), json!({ "name": "b.exe" })));
+        assert!(covers(json!({ "name": "*.exe" }), json!({ "name": "a.exe" })));
+        assert!(covers(json!({ "name": { "regex": "^a" } }), json!({ "name": "abc.exe" })));
+        assert!(!covers(json!({ "name": { "regex": "(?-i)^a" } }), json!({ "name": "abc.exe" })));
+    }
+
+    #[test]
+    fn covers_globs() {
+        let covers = |a: serde_json::Value, b: serde_json::Value| condition(a).covers(&condition(b));
+
+        assert!(covers(json!({ "name": "*.exe" }), json!({ "name": "*.exe" })));
+        // one glob containing another isn't worked out
+        assert!(!covers(json!({ "name": "*.exe" }), json!({ "name": "a*.exe" })));
+        // an exact name can't cover a glob, even one that only matches it
+        assert!(!covers(json!({ "name": "a.exe" }), json!({ "name": "a.ex[e]" })));
+    }
+
+    #[test]
+    fn covers_fields() {
+        let covers = |a: serde_json::Value, b: serde_json::Value| condition(a).covers(&condition(b));
+
+        // fewer fields is looser
+        assert!(covers(json!({ "name": "a.exe" }), json!({ "name": "a.exe", "user": "bob" })));
+        assert!(!covers(json!({ "name": "a.exe", "user": "bob" }), json!({ "name": "a.exe" })));
+        assert!(covers(json!({ "session": 1 }), json!({ "name": "a.exe", "session": 1 })));
+        assert!(!covers(json!({ "session": 1 }), json!({ "session": 2 })));
+
+        // nested conditions only when they're the same
+        assert!(covers(json!({ "name": "*", "any": [{ "user": "bob" }] }), json!({ "name": "a.exe", "any": [{ "user": "bob" }] })));
+        assert!(!covers(json!({ "any": [{ "user": "bob" }] }), json!({ "any": [{ "user": "alice" }] })));
+        assert!(covers(json!({ "name": "a.exe" }), json!({ "name": "a.exe", "all": [{ "session": 0 }] })));
+    }
+}
@@ -0,0 +1,332 @@
+use crate::config::{MAX_POLL_INTERVAL, MIN_POLL_INTERVAL, VERSION};
+
+use serde_json::{json, Value};
+
+
+/// A JSON Schema for the current version of the config, so editors can autocomplete and check it.
+/// `check` also goes by it to find unknown settings, so anything added to the config has to be added here too.
+pub fn schema() -> Value {
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "Annoying Process Killer config",
+        "type": "object",
+        "properties": {
+            "$schema": {
+                "description": "Where to find this schema",
+                "type": "string"
+            },
+            "version": {
+                "description": "The version of the config this is written for",
+                "const": VERSION
+            },
+            "rules": {
+                "description": "Act on anything matching one of these, killing it unless the rule says otherwise",
+                "type": "array",
+                "items": { "$ref": "#/definitions/rule" }
+            },
+            "allow": {
+                "description": "Never act on anything matching one of these, even if a rule matches",
+                "type": "array",
+                "items": { "$ref": "#/definitions/rule" }
+            },
+            "poll_interval": {
+                "description": "Seconds between checks for new processes, when they have to be polled for",
+                "type": "number",
+                "minimum": MIN_POLL_INTERVAL.as_secs_f64(),
+                "maximum": MAX_POLL_INTERVAL.as_secs_f64(),
+                "default": 2
+            }
+        },
+        "required": ["version"],
+        "additionalProperties": false,
+
+        "definitions": {
+            "pattern": {
+                "description": "An exact name, a glob if it has any of `*?[` in it, or a regex. Always case insensitive.",
+                "anyOf": [
+                    { "type": "string" },
+                    {
+                        "type": "object",
+                        "properties": { "glob": { "type": "string" } },
+                        "required": ["glob"],
+                        "additionalProperties": false
+                    },
+                    {
+                        "type": "object",
+                        "properties": { "regex": { "type": "string" } },
+                        "required": ["regex"],
+                        "additionalProperties": false
+                    }
+                ]
+            },
+
+            "condition": {
+                "description": "Everything set has to match, as do all of `all`, and at least one of `any`",
+                "type": "object",
+                "properties": {
+                    "name": { "$ref": "#/definitions/pattern" },
+                    "path": {
+                        "description": "Full path of the executable",
+                        "$ref": "#/definitions/pattern"
+                    },
+                    "cmdline": { "$ref": "#/definitions/pattern" },
+                    "parent_name": { "$ref": "#/definitions/pattern" },
+                    "session": { "type": "integer", "minimum": 0 },
+                    "user": {
+                        "description": "Matches either `DOMAIN\\user` or just `user` on Windows",
+                        "$ref": "#/definitions/pattern"
+                    },
+                    "all": {
+                        "type": "array",
+                        "items": { "$ref": "#/definitions/condition" }
+                    },
+                    "any": {
+                        "type": "array",
+                        "items": { "$ref": "#/definitions/condition" }
+                    }
+                },
+                "minProperties": 1,
+                "additionalProperties": false
+            },
+
+            "action": {
+                "description": "What to do to a process the rule matches, `terminate` if it isn't set",
+                "anyOf": [
+                    { "enum": ["terminate", "suspend", "log"] },
+                    {
+                        "type": "object",
+                        "properties": {
+                            "terminate": {
+                                "type": "object",
+                                "properties": {
+                                    "exit_code": {
+                                        "description": "Only used on Windows",
+                                        "type": "integer",
+                                        "minimum": 0
+                                    }
+                                },
+                                "additionalProperties": false
+                            }
+                        },
+                        "required": ["terminate"],
+                        "additionalProperties": false
+                    },
+                    {
+                        "type": "object",
+                        "properties": {
+                            "priority": { "enum": ["idle", "below_normal", "normal", "above_normal", "high", "realtime"] }
+                        },
+                        "required": ["priority"],
+                        "additionalProperties": false
+                    },
+                    {
+                        "type": "object",
+                        "properties": {
+                            "affinity": {
+                                "description": "Only let it run on these CPUs",
+                                "type": "array",
+                                "items": { "type": "integer", "minimum": 0 }
+                            }
+                        },
+                        "required": ["affinity"],
+                        "additionalProperties": false
+                    }
+                ]
+            },
+
+            "threshold": {
+                "description": "Only act once the process has stayed over every limit that's set for a while",
+                "type": "object",
+                "properties": {
+                    "memory": {
+                        "description": "Working set in bytes, or a size like `1GiB` or `500MB`",
+                        "anyOf": [
+                            { "type": "integer", "minimum": 0 },
+                            { "type": "string", "pattern": "^[0-9.]+\\s*([Bb]|[KkMmGg][Ii]?[Bb])?\\s*$" }
+                        ]
+                    },
+                    "cpu": {
+                        "description": "Percent of all CPUs together",
+                        "type": "number",
+                        "minimum": 0
+                    },
+                    "for": {
+                        "description": "Seconds it has to stay over before the rule acts",
+                        "type": "number",
+                        "minimum": 0
+                    }
+                },
+                "anyOf": [
+                    { "required": ["memory"] },
+                    { "required": ["cpu"] }
+                ],
+                "additionalProperties": false
+            },
+
+            "rule": {
+                "type": "object",
+                "properties": {
+                    "id": {
+                        "description": "Shows up in the log when this rule is the one that decided",
+                        "type": "string"
+                    },
+                    "match": { "$ref": "#/definitions/condition" },
+                    "action": {
+                        "description": "Only used by kill rules",
+                        "$ref": "#/definitions/action"
+                    },
+                    "audit": {
+                        "description": "Only log what this rule would do",
+                        "type": "boolean",
+                        "default": false
+                    },
+                    "tree": {
+                        "description": "Do the same to every descendant of the process, children first",
+                        "type": "boolean",
+                        "default": false
+                    },
+                    "threshold": {
+                        "description": "Only for kill rules",
+                        "$ref": "#/definitions/threshold"
+                    }
+                },
+                "required": ["match"],
+                "additionalProperties": false
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use regex::Regex;
+
+    /// Whether `value` fits `schema`, for the parts of JSON Schema that [`schema`] uses
+    fn valid(root: &Value, schema: &Value, value: &Value) -> bool {
+        if let Some(path) = schema.get("$ref").and_then(Value::as_str) {
+            let name = path.trim_start_matches("#/definitions/");
+            if !valid(root, &root["definitions"][name], value) {
+                return false;
+            }
+        }
+
+        if let Some(branches) = schema.get("anyOf").and_then(Value::as_array) {
+            if !branches.iter().any(|v| valid(root, v, value)) {
+                return false;
+            }
+        }
+
+        let fits_type = match schema.get("type").and_then(Value::as_str) {
+            Some("object") => value.is_object(),
+            Some("array") => value.is_array(),
+            Some("string") => value.is_string(),
+            Some("number") => value.is_number(),
+            Some("integer") => value.is_i64() || value.is_u64(),
+            Some("boolean") => value.is_boolean(),
+            Some(v) => panic!("unknown type {v}"),
+            None => true
+        };
+
+        let fits_const = schema.get("const").is_none_or(|v| v == value);
+        let fits_enum = schema.get("enum").and_then(Value::as_array).is_none_or(|v| v.contains(value));
+
+        let fits_range = match value.as_f64() {
+            Some(n) => {
+                schema.get("minimum").and_then(Value::as_f64).is_none_or(|v| n >= v)
+                    && schema.get("maximum").and_then(Value::as_f64).is_none_or(|v| n <= v)
+            }
+            None => true
+        };
+
+        let fits_pattern = match (schema.get("pattern").and_then(Value::as_str), value.as_str()) {
+            (Some(pattern), Some(v)) => Regex::new(pattern).unwrap().is_match(v),
+            _ => true
+        };
+
+        if !(fits_type && fits_const && fits_enum && fits_range && fits_pattern) {
+            return false;
+        }
+
+        match value {
+            Value::Object(map) => {
+                let properties = schema.get("properties").and_then(Value::as_object);
+                let required = schema.get("required").and_then(Value::as_array).map_or(&[][..], Vec::as_slice);
+                let closed = schema.get("additionalProperties") == Some(&Value::Bool(false));
+                let min = schema.get("minProperties").and_then(Value::as_u64).unwrap_or(0);
+
+                required.iter().filter_map(Value::as_str).all(|v| map.contains_key(v))
+                    && map.len() as u64 >= min
+                    && map.iter().all(|(key, value)| match properties.and_then(|v| v.get(key)) {
+                        Some(schema) => valid(root, schema, value),
+                        None => !closed
+                    })
+            }
+
+            Value::Array(items) => match schema.get("items") {
+                Some(schema) => items.iter().all(|v| valid(root, schema, v)),
+                None => true
+            },
+
+            _ => true
+        }
+    }
+
+    fn validate(value: &Value) -> bool {
+        let schema = schema();
+        valid(&schema, &schema, value)
+    }
+
+    #[test]
+    fn default_config_is_valid() {
+        let config: Value = serde_json::from_str(&crate::config::DEFAULT).unwrap();
+        assert!(validate(&config));
+    }
+
+    #[test]
+    fn every_setting_is_valid() {
+        let config = json!({
+            "$schema": "./config.schema.json",
+            "version": VERSION,
+            "rules": [
+                { "match": { "name": "CompatTelRunner.exe" } },
+                { "match": { "name": { "regex": "^updater\\d*\\.exe$" }, "session": 1 }, "action": { "priority": "idle" } },
+                { "match": { "any": [{ "parent_name": "launcher.exe" }, { "user": "bob" }] }, "action": { "affinity": [0, 1] }, "tree": true },
+                { "id": "big", "match": { "cmdline": { "glob": "*--big*" } }, "threshold": { "memory": "1GiB", "cpu": 80, "for": 30 } },
+                { "match": { "all": [{ "path": "C:\\*" }] }, "action": { "terminate": { "exit_code": 1 } }, "audit": true },
+                { "match": { "name": "a.exe" }, "action": "suspend" }
+            ],
+            "allow": [
+                { "id": "ours", "match": { "path": "C:\\Program Files\\OurVendor\\*" } }
+            ],
+            "poll_interval": 0.5
+        });
+
+        assert!(validate(&config));
+    }
+
+    #[test]
+    fn invalid_configs() {
+        let invalid = [
+            // no version
+            json!({ "rules": [] }),
+            json!({ "version": 1, "processes": ["a.exe"] }),
+            json!({ "version": VERSION, "rules": [{ "match": { "nmae": "a.exe" } }] }),
+            json!({ "version": VERSION, "rules": [{ "match": {} }] }),
+            json!({ "version": VERSION, "rules": [{ "match": { "name": { "glob": "*", "regex": ".*" } } }] }),
+            json!({ "version": VERSION, "rules": [{ "match": { "session": -1 } }] }),
+            json!({ "version": VERSION, "rules": [{ "match": { "name": "a.exe" }, "action": "explode" }] }),
+            json!({ "version": VERSION, "rules": [{ "match": { "name": "a.exe" }, "action": { "priority": "fast" } }] }),
+            json!({ "version": VERSION, "rules": [{ "match": { "name": "a.exe" }, "threshold": { "for": 30 } }] }),
+            json!({ "version": VERSION, "rules": [{ "match": { "name": "a.exe" }, "threshold": { "memory": "lots" } }] }),
+            json!({ "version": VERSION, "rules": [{ "match": { "name": "a.exe" }, "threshold": { "cpu": -5 } }] }),
+            json!({ "version": VERSION, "poll_interval": 0 }),
+            json!({ "version": VERSION, "poll_interval": "2" })
+        ];
+
+        for config in invalid {
+            assert!(!validate(&config), "{config}");
+        }
+    }
+}
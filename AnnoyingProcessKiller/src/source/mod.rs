@@ -0,0 +1,76 @@
+#[cfg(windows)]
+mod wmi;
+#[cfg(windows)]
+pub use wmi::{WmiSource, WmiUsage};
+
+#[cfg(target_os = "linux")]
+pub mod procfs;
+#[cfg(target_os = "linux")]
+pub use procfs::{ProcSource, ProcUsage};
+
+#[cfg(target_os = "linux")]
+mod netlink;
+#[cfg(target_os = "linux")]
+pub use netlink::NetlinkSource;
+
+use std::error::Error;
+
+use async_channel::Receiver;
+use thiserror::Error;
+
+
+/// A process as seen by an event source, independent of where it came from
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProcessInfo {
+    pub name: String,
+    pub pid: u32,
+    pub parent_pid: u32,
+    pub parent_name: Option<String>,
+    pub executable_path: Option<String>,
+    pub command_line: Option<String>,
+    pub session_id: Option<u32>,
+    pub user: Option<String>,
+    /// When it started, in whatever the platform counts in, so only good for comparing with other processes'
+    pub start_time: Option<u64>
+}
+
+#[derive(Error, Debug)]
+pub enum SourceError {
+    #[error("Failed to start event source -> {0}")]
+    StartFailed(String),
+
+    #[error("Event source is already running")]
+    AlreadyStarted,
+
+    #[error("Event source stopped on its own {0} times in a row, giving up")]
+    KeptStopping(u32)
+}
+
+/// Something that can tell us about processes as they start
+pub trait ProcessEventSource {
+    /// Begin watching. Every process start is sent on the returned channel
+    /// until `stop` is called, after which the channel is closed.
+    fn start(&mut self) -> Result<Receiver<ProcessInfo>, Box<dyn Error>>;
+
+    fn stop(&mut self);
+
+    /// Everything that was already running, which `start` won't report.
+    /// Call it after `start`, so nothing slips through between the two.
+    fn running(&mut self) -> Result<Vec<ProcessInfo>, Box<dyn Error>>;
+}
+
+/// An in-memory source: events are whatever gets sent on the other half of the channel
+#[cfg(test)]
+impl ProcessEventSource for Receiver<ProcessInfo> {
+    fn start(&mut self) -> Result<Receiver<ProcessInfo>, Box<dyn Error>> {
+        Ok(self.clone())
+    }
+
+    fn stop(&mut self) {
+        self.close();
+    }
+
+    fn running(&mut self) -> Result<Vec<ProcessInfo>, Box<dyn Error>> {
+        Ok(Vec::new())
+    }
+}
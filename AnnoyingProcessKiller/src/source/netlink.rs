@@ -0,0 +1,384 @@
+use super::{procfs::{self, read_process, read_stat}, ProcessEventSource, ProcessInfo, SourceError};
+
+use std::{
+    collections::HashMap, error::Error, io, mem,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    sync::{atomic::{AtomicBool, Ordering}, Arc},
+    thread::JoinHandle
+};
+
+use async_channel::{unbounded, Receiver, Sender};
+
+
+// linux/connector.h, linux/cn_proc.h
+const CN_IDX_PROC: u32 = 1;
+const CN_VAL_PROC: u32 = 1;
+const PROC_CN_MCAST_LISTEN: u32 = 1;
+const PROC_CN_MCAST_IGNORE: u32 = 2;
+
+const PROC_EVENT_EXEC: u32 = 0x00000002;
+
+const NLMSG_HDRLEN: usize = 16;
+const CN_MSG_LEN: usize = 20;
+// what, cpu, timestamp_ns
+const PROC_EVENT_HDRLEN: usize = 16;
+
+/// How often the reader thread checks whether it should stop
+const READ_TIMEOUT_MS: libc::suseconds_t = 250;
+
+/// Watches for new processes through the kernel's netlink process connector.
+/// Unlike [`super::ProcSource`] every exec is seen the moment it happens, but it needs `CAP_NET_ADMIN`.
+pub struct NetlinkSource {
+    socket: Arc<OwnedFd>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>
+}
+
+impl NetlinkSource {
+    /// Connects and subscribes to the proc connector, failing if we aren't allowed to
+    pub fn open() -> io::Result<Self> {
+        let socket = unsafe {
+            let fd = libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+                libc::NETLINK_CONNECTOR
+            );
+
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            OwnedFd::from_raw_fd(fd)
+        };
+
+        let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        addr.nl_groups = CN_IDX_PROC;
+
+        let timeout = libc::timeval {
+            tv_sec: 0,
+            tv_usec: READ_TIMEOUT_MS * 1000
+        };
+
+        unsafe {
+            if libc::bind(
+                socket.as_raw_fd(),
+                &addr as *const _ as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t
+            ) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            if libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                &timeout as *const _ as *const libc::c_void,
+                mem::size_of::<libc::timeval>() as libc::socklen_t
+            ) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        let socket = Arc::new(socket);
+        subscribe(&socket, PROC_CN_MCAST_LISTEN)?;
+
+        Ok(Self {
+            socket,
+            stop: Arc::new(AtomicBool::new(false)),
+            thread: None
+        })
+    }
+}
+
+impl ProcessEventSource for NetlinkSource {
+    fn start(&mut self) -> Result<Receiver<ProcessInfo>, Box<dyn Error>> {
+        if self.thread.is_some() {
+            return Err(Box::new(SourceError::AlreadyStarted));
+        }
+
+        self.stop.store(false, Ordering::Relaxed);
+
+        // start times of what's been seen, to tell what was missed if events get dropped
+        let mut known = start_times().map_err(|e| SourceError::StartFailed(e.to_string()))?;
+
+        let (tx, rx) = unbounded();
+        let socket = self.socket.clone();
+        let stop = self.stop.clone();
+
+        let thread = std::thread::spawn(move || {
+            let mut buf = [0u8; 4096];
+
+            while !stop.load(Ordering::Relaxed) {
+                let len = unsafe {
+                    libc::recv(socket.as_raw_fd(), buf.as_mut_ptr() as *mut libc::c_void, buf.len(), 0)
+                };
+
+                if len < 0 {
+                    let e = io::Error::last_os_error();
+                    match e.kind() {
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted => continue,
+
+                        // the socket's buffer overflowed and some events are lost, but it still works
+                        _ if e.raw_os_error() == Some(libc::ENOBUFS) => {
+                            println!("Warning: Missed process events, checking /proc for anything that started");
+                            if !rescan(&mut known, &tx) {
+                                return;
+                            }
+
+                            continue;
+                        }
+
+                        _ => {
+                            println!("Warning: Failed to read from proc connector: {e}");
+                            break;
+                        }
+                    }
+                }
+
+                for tgid in exec_events(&buf[..len as usize]) {
+                    if let Ok(stat) = read_stat(tgid) {
+                        known.insert(tgid, stat.start_time);
+                    }
+
+                    // it may already be gone by now
+                    if let Ok(process) = read_process(tgid) {
+                        if tx.try_send(process).is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+
+            tx.close();
+        });
+
+        self.thread = Some(thread);
+
+        Ok(rx)
+    }
+
+    fn stop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+
+    fn running(&mut self) -> Result<Vec<ProcessInfo>, Box<dyn Error>> {
+        Ok(procfs::running()?)
+    }
+}
+
+impl Drop for NetlinkSource {
+    fn drop(&mut self) {
+        self.stop();
+        let _ = subscribe(&self.socket, PROC_CN_MCAST_IGNORE);
+    }
+}
+
+/// The start time of everything running, by pid
+fn start_times() -> io::Result<HashMap<u32, u64>> {
+    Ok(procfs::scan()?.into_iter().map(|(pid, stat)| (pid, stat.start_time)).collect())
+}
+
+/// Send everything running that isn't in `known`, then make that what's known.
+/// False once nothing is listening anymore.
+fn rescan(known: &mut HashMap<u32, u64>, tx: &Sender<ProcessInfo>) -> bool {
+    let current = match start_times() {
+        Ok(v) => v,
+        Err(e) => {
+            println!("Warning: Failed to scan /proc: {e}");
+            return true;
+        }
+    };
+
+    for (&pid, &start_time) in &current {
+        // a different start time means the pid got reused
+        if known.get(&pid) == Some(&start_time) {
+            continue;
+        }
+
+        // it may already be gone by now
+        if let Ok(process) = read_process(pid) {
+            if tx.try_send(process).is_err() {
+                return false;
+            }
+        }
+    }
+
+    *known = current;
+
+    true
+}
+
+/// Tell the connector to start or stop sending us events
+fn subscribe(socket: &OwnedFd, op: u32) -> io::Result<()> {
+    let len = NLMSG_HDRLEN + CN_MSG_LEN + mem::size_of::<u32>();
+    let mut msg = Vec::with_capacity(len);
+
+    // nlmsghdr
+    msg.extend_from_slice(&(len as u32).to_ne_bytes());
+    msg.extend_from_slice(&(libc::NLMSG_DONE as u16).to_ne_bytes());
+    msg.extend_from_slice(&0u16.to_ne_bytes());
+    msg.extend_from_slice(&0u32.to_ne_bytes());
+    msg.extend_from_slice(&0u32.to_ne_bytes());
+
+    // cn_msg
+    msg.extend_from_slice(&CN_IDX_PROC.to_ne_bytes());
+    msg.extend_from_slice(&CN_VAL_PROC.to_ne_bytes());
+    msg.extend_from_slice(&0u32.to_ne_bytes());
+    msg.extend_from_slice(&0u32.to_ne_bytes());
+    msg.extend_from_slice(&(mem::size_of::<u32>() as u16).to_ne_bytes());
+    msg.extend_from_slice(&0u16.to_ne_bytes());
+
+    msg.extend_from_slice(&op.to_ne_bytes());
+
+    let res = unsafe {
+        libc::send(socket.as_raw_fd(), msg.as_ptr() as *const libc::c_void, msg.len(), 0)
+    };
+
+    if res < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// The tgid of every process that exec'd in one datagram.
+/// A fork is only a copy of a process we've already judged, and an exit doesn't start
+/// anything, so `PROC_EVENT_FORK`, `PROC_EVENT_EXIT` and the rest are skipped.
+pub fn exec_events(buf: &[u8]) -> Vec<u32> {
+    let u16_at = |b: &[u8], i: usize| b.get(i..i + 2).map(|v| u16::from_ne_bytes([v[0], v[1]]));
+    let u32_at = |b: &[u8], i: usize| b.get(i..i + 4).map(|v| u32::from_ne_bytes([v[0], v[1], v[2], v[3]]));
+
+    let mut tgids = Vec::new();
+    let mut offset = 0;
+
+    while let (Some(msg_len), Some(msg_type)) = (u32_at(buf, offset), u16_at(buf, offset + 4)) {
+        let msg_len = msg_len as usize;
+        if msg_len < NLMSG_HDRLEN || offset + msg_len > buf.len() {
+            break;
+        }
+
+        let msg = &buf[offset..offset + msg_len];
+        // messages are padded out to 4 bytes
+        offset += (msg_len + 3) & !3;
+
+        if msg_type != libc::NLMSG_DONE as u16 {
+            continue;
+        }
+
+        let cn_msg = &msg[NLMSG_HDRLEN..];
+        if u32_at(cn_msg, 0) != Some(CN_IDX_PROC) || u32_at(cn_msg, 4) != Some(CN_VAL_PROC) {
+            continue;
+        }
+
+        let event = &cn_msg[CN_MSG_LEN.min(cn_msg.len())..];
+        let data = &event[PROC_EVENT_HDRLEN.min(event.len())..];
+
+        if u32_at(event, 0) == Some(PROC_EVENT_EXEC) {
+            // process_pid, process_tgid
+            tgids.extend(u32_at(data, 4));
+        }
+    }
+
+    tgids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROC_EVENT_FORK: u32 = 0x00000001;
+
+    /// One netlink message from the process connector, laid out like the kernel sends it
+    fn message(what: u32, data: &[u32]) -> Vec<u8> {
+        let len = NLMSG_HDRLEN + CN_MSG_LEN + PROC_EVENT_HDRLEN + data.len() * 4;
+        let mut buf = Vec::with_capacity(len);
+
+        // nlmsghdr: len, type, flags, seq, pid
+        buf.extend((len as u32).to_ne_bytes());
+        buf.extend((libc::NLMSG_DONE as u16).to_ne_bytes());
+        buf.extend(0u16.to_ne_bytes());
+        buf.extend([0; 8]);
+
+        // cn_msg: idx, val, seq, ack, len, flags
+        buf.extend(CN_IDX_PROC.to_ne_bytes());
+        buf.extend(CN_VAL_PROC.to_ne_bytes());
+        buf.extend([0; 8]);
+        buf.extend(((PROC_EVENT_HDRLEN + data.len() * 4) as u16).to_ne_bytes());
+        buf.extend(0u16.to_ne_bytes());
+
+        // proc_event: what, cpu, timestamp_ns
+        buf.extend(what.to_ne_bytes());
+        buf.extend([0; 12]);
+
+        for v in data {
+            buf.extend(v.to_ne_bytes());
+        }
+
+        buf
+    }
+
+    fn exec(pid: u32, tgid: u32) -> Vec<u8> {
+        message(PROC_EVENT_EXEC, &[pid, tgid])
+    }
+
+    #[test]
+    fn one_exec() {
+        assert_eq!(exec_events(&exec(101, 100)), [100]);
+    }
+
+    #[test]
+    fn fork_then_exec() {
+        // parent pid and tgid, then child pid and tgid
+        let mut buf = message(PROC_EVENT_FORK, &[1, 1, 200, 200]);
+        buf.extend(exec(200, 200));
+
+        assert_eq!(exec_events(&buf), [200]);
+    }
+
+    #[test]
+    fn other_connectors_are_skipped() {
+        let mut buf = exec(300, 300);
+        buf[NLMSG_HDRLEN..NLMSG_HDRLEN + 4].copy_from_slice(&7u32.to_ne_bytes());
+        buf.extend(exec(301, 301));
+
+        assert_eq!(exec_events(&buf), [301]);
+    }
+
+    #[test]
+    fn truncated_message() {
+        let mut buf = exec(400, 400);
+        let second = exec(401, 401);
+        buf.extend(&second[..second.len() - 4]);
+
+        assert_eq!(exec_events(&buf), [400]);
+        assert_eq!(exec_events(&buf[..10]), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn length_shorter_than_header() {
+        let mut buf = exec(500, 500);
+        let mut short = exec(501, 501);
+        short[..4].copy_from_slice(&((NLMSG_HDRLEN - 1) as u32).to_ne_bytes());
+        buf.extend(short);
+
+        assert_eq!(exec_events(&buf), [500]);
+
+        // a zero length would never move on
+        let mut zero = exec(502, 502);
+        zero[..4].copy_from_slice(&0u32.to_ne_bytes());
+        assert_eq!(exec_events(&zero), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn exec_without_its_data() {
+        let mut buf = message(PROC_EVENT_EXEC, &[600]);
+        buf.extend(exec(601, 601));
+
+        assert_eq!(exec_events(&buf), [601]);
+    }
+}
@@ -0,0 +1,399 @@
+use super::{ProcessEventSource, ProcessInfo, SourceError};
+use crate::{monitor::{Sample, UsageSampler}, threshold::{ProcessKey, Usage}};
+
+use std::{
+    collections::HashMap, error::Error, ffi::CStr, fs, io, path::Path,
+    sync::mpsc::{self, RecvTimeoutError}, thread::JoinHandle, time::Duration
+};
+
+use async_channel::{unbounded, Receiver};
+
+
+/// Same as how often the WMI source has WMI check
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Watches for new processes by periodically scanning `/proc`
+pub struct ProcSource {
+    interval: Duration,
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>
+}
+
+impl ProcSource {
+    pub fn new() -> Self {
+        Self::with_interval(DEFAULT_INTERVAL)
+    }
+
+    pub fn with_interval(interval: Duration) -> Self {
+        Self {
+            interval,
+            stop: None,
+            thread: None
+        }
+    }
+}
+
+impl Default for ProcSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProcessEventSource for ProcSource {
+    fn start(&mut self) -> Result<Receiver<ProcessInfo>, Box<dyn Error>> {
+        if self.thread.is_some() {
+            return Err(Box::new(SourceError::AlreadyStarted));
+        }
+
+        // whatever is already running didn't just start
+        let mut known = scan().map_err(|e| SourceError::StartFailed(e.to_string()))?;
+
+        let (tx, rx) = unbounded();
+        let (stop_tx, stop_rx) = mpsc::channel::<()>();
+        let interval = self.interval;
+
+        let thread = std::thread::spawn(move || {
+            // anything else means we were told to stop, or the source was dropped
+            while let Err(RecvTimeoutError::Timeout) = stop_rx.recv_timeout(interval) {
+                let current = match scan() {
+                    Ok(v) => v,
+                    Err(e) => {
+                        println!("Warning: Failed to scan /proc: {e}");
+                        continue;
+                    }
+                };
+
+                for (&pid, stat) in &current {
+                    // a different start time means the pid got reused
+                    if known.get(&pid).map(|v| v.start_time) == Some(stat.start_time) {
+                        continue;
+                    }
+
+                    // it may already be gone by now
+                    if let Ok(process) = read_process(pid) {
+                        if tx.try_send(process).is_err() {
+                            return;
+                        }
+                    }
+                }
+
+                known = current;
+            }
+
+            tx.close();
+        });
+
+        self.stop = Some(stop_tx);
+        self.thread = Some(thread);
+
+        Ok(rx)
+    }
+
+    fn stop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+
+    fn running(&mut self) -> Result<Vec<ProcessInfo>, Box<dyn Error>> {
+        Ok(running()?)
+    }
+}
+
+impl Drop for ProcSource {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// The interesting parts of `/proc/<pid>/stat`
+pub struct Stat {
+    pub comm: String,
+    pub ppid: u32,
+    pub start_time: u64,
+    /// User and kernel time together, in clock ticks
+    pub cpu_ticks: u64,
+    /// Resident memory, in pages
+    pub rss_pages: u64
+}
+
+pub fn read_stat(pid: u32) -> io::Result<Stat> {
+    let stat = fs::read_to_string(format!("/proc/{pid}/stat"))?;
+
+    parse_stat(&stat).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("malformed /proc/{pid}/stat")))
+}
+
+fn parse_stat(stat: &str) -> Option<Stat> {
+    // comm is wrapped in parens and can itself contain spaces and parens
+    let open = stat.find('(')?;
+    let close = stat.rfind(')')?;
+    let comm = stat.get(open + 1..close)?.to_string();
+
+    // fields after comm, starting at field 3 (state)
+    let fields: Vec<&str> = stat[close + 1..].split_whitespace().collect();
+    let field = |i: usize| fields.get(i).and_then(|v| v.parse::<u64>().ok());
+
+    Some(Stat {
+        comm,
+        ppid: fields.get(1)?.parse().ok()?,
+        start_time: field(19)?,
+        // utime + stime
+        cpu_ticks: field(11)? + field(12)?,
+        rss_pages: field(21)?
+    })
+}
+
+/// Samples usage out of `/proc`, reading the rest of each process only the first time it's seen
+#[derive(Default)]
+pub struct ProcUsage {
+    known: HashMap<ProcessKey, ProcessInfo>
+}
+
+impl UsageSampler for ProcUsage {
+    fn sample(&mut self) -> Result<Vec<Sample>, Box<dyn Error>> {
+        let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) }.max(1) as f64;
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.max(0) as u64;
+
+        let mut samples = Vec::new();
+        let mut known = HashMap::new();
+
+        for (pid, stat) in scan()? {
+            let key = (pid, stat.start_time);
+
+            let process = match self.known.remove(&key) {
+                Some(v) => v,
+                // it may already be gone by now
+                None => match read_process(pid) {
+                    Ok(v) => v,
+                    Err(_) => continue
+                }
+            };
+
+            samples.push(Sample {
+                key,
+                process: process.clone(),
+                usage: Usage {
+                    memory: stat.rss_pages * page_size,
+                    cpu_time: Duration::from_secs_f64(stat.cpu_ticks as f64 / ticks)
+                }
+            });
+
+            known.insert(key, process);
+        }
+
+        self.known = known;
+
+        Ok(samples)
+    }
+}
+
+/// Every pid in `/proc` along with its stat
+pub fn scan() -> io::Result<HashMap<u32, Stat>> {
+    let mut pids = HashMap::new();
+
+    for entry in fs::read_dir("/proc")? {
+        let pid = match entry?.file_name().to_str().and_then(|v| v.parse::<u32>().ok()) {
+            Some(v) => v,
+            None => continue
+        };
+
+        // processes can exit while we're looking at them
+        if let Ok(stat) = read_stat(pid) {
+            pids.insert(pid, stat);
+        }
+    }
+
+    Ok(pids)
+}
+
+/// Everything currently running, read the same way as new processes are
+pub fn running() -> io::Result<Vec<ProcessInfo>> {
+    let mut pids: Vec<u32> = scan()?.into_keys().collect();
+    pids.sort_unstable();
+
+    // it may already be gone by now
+    Ok(pids.into_iter().filter_map(|pid| read_process(pid).ok()).collect())
+}
+
+/// Read everything we know about `pid` out of `/proc`
+pub fn read_process(pid: u32) -> io::Result<ProcessInfo> {
+    let stat = read_stat(pid)?;
+
+    // exe is only readable with enough privileges, and is empty for kernel threads
+    let executable_path = fs::read_link(format!("/proc/{pid}/exe"))
+        .ok()
+        .map(|v| v.to_string_lossy().into_owned());
+
+    let command_line = fs::read(format!("/proc/{pid}/cmdline"))
+        .ok()
+        .filter(|v| !v.is_empty())
+        .map(|v| {
+            v.split(|&b| b == 0)
+                .filter(|arg| !arg.is_empty())
+                .map(String::from_utf8_lossy)
+                .collect::<Vec<_>>()
+                .join(" ")
+        });
+
+    let name = process_name(&stat.comm, executable_path.as_deref(), command_line.as_deref());
+
+    let session_id = fs::read_to_string(format!("/proc/{pid}/sessionid"))
+        .ok()
+        .and_then(|v| v.trim().parse::<u32>().ok())
+        // unset audit session
+        .filter(|&v| v != u32::MAX);
+
+    let user = fs::read_to_string(format!("/proc/{pid}/status"))
+        .ok()
+        .and_then(|status| {
+            status.lines()
+                .find_map(|line| line.strip_prefix("Uid:"))
+                .and_then(|uids| uids.split_whitespace().next())
+                .and_then(|uid| uid.parse::<u32>().ok())
+        })
+        .map(|uid| user_name(uid).unwrap_or_else(|| uid.to_string()));
+
+    Ok(ProcessInfo {
+        name,
+        pid,
+        parent_pid: stat.ppid,
+        parent_name: read_name(stat.ppid),
+        executable_path,
+        command_line,
+        session_id,
+        user,
+        start_time: Some(stat.start_time)
+    })
+}
+
+/// Just the name of `pid`, the same way `read_process` works it out
+fn read_name(pid: u32) -> Option<String> {
+    let stat = read_stat(pid).ok()?;
+    let executable_path = fs::read_link(format!("/proc/{pid}/exe")).ok();
+    let command_line = fs::read(format!("/proc/{pid}/cmdline")).ok();
+
+    Some(process_name(
+        &stat.comm,
+        executable_path.as_deref().map(|v| v.to_string_lossy()).as_deref(),
+        command_line.as_deref()
+            .and_then(|v| v.split(|&b| b == 0).next())
+            .map(String::from_utf8_lossy)
+            .as_deref()
+    ))
+}
+
+/// comm is cut off at 15 characters, so prefer the executable's file name when we can see it
+fn process_name(comm: &str, executable_path: Option<&str>, command_line: Option<&str>) -> String {
+    let file_name = |path: &str| {
+        Path::new(path)
+            .file_name()
+            .map(|v| v.to_string_lossy().into_owned())
+    };
+
+    // the kernel adds this to exe once the file is gone, like after an update replaced it
+    let executable_path = executable_path.map(|v| v.strip_suffix(" (deleted)").unwrap_or(v));
+
+    if let Some(name) = executable_path.and_then(file_name) {
+        return name;
+    }
+
+    // argv[0] is only a guess since processes can rewrite it, so it has to agree with comm
+    if let Some(name) = command_line.and_then(|v| v.split(' ').next()).and_then(file_name) {
+        if name.starts_with(comm) {
+            return name;
+        }
+    }
+
+    comm.to_string()
+}
+
+fn user_name(uid: u32) -> Option<String> {
+    let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0 as libc::c_char; 4096];
+    let mut result = std::ptr::null_mut();
+
+    let res = unsafe {
+        libc::getpwuid_r(uid, &mut pwd, buf.as_mut_ptr(), buf.len(), &mut result)
+    };
+
+    if res != 0 || result.is_null() {
+        return None;
+    }
+
+    let name = unsafe { CStr::from_ptr(pwd.pw_name) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `stat` line with every field numbered after where it is, so it's clear which one was read
+    fn stat_line(comm: &str) -> String {
+        let fields: Vec<String> = (4..=52).map(|i| i.to_string()).collect();
+        format!("1234 ({comm}) S {}", fields.join(" "))
+    }
+
+    #[test]
+    fn stat_fields() {
+        let stat = parse_stat(&stat_line("bash")).unwrap();
+
+        assert_eq!(stat.comm, "bash");
+        // fields 4, 14 + 15, 22 and 24
+        assert_eq!(stat.ppid, 4);
+        assert_eq!(stat.cpu_ticks, 14 + 15);
+        assert_eq!(stat.start_time, 22);
+        assert_eq!(stat.rss_pages, 24);
+    }
+
+    #[test]
+    fn stat_comm_with_parens_and_spaces() {
+        for comm in ["a b", "x) S 1 2 (y", ")", "(sd-pam)", ""] {
+            let stat = parse_stat(&stat_line(comm)).unwrap();
+
+            assert_eq!(stat.comm, comm);
+            assert_eq!((stat.ppid, stat.start_time), (4, 22), "{comm}");
+        }
+    }
+
+    #[test]
+    fn real_stat() {
+        let line = "81 (Web Content) S 1 81 81 0 -1 4194560 4096 0 0 0 250 36 0 0 20 0 31 0 5373 2883584000 58000 \
+                    18446744073709551615 1 1 0 0 0 0 0 4096 0 0 0 17 3 0 0 0 0 0 0 0 0 0 0 0 0 0\n";
+        let stat = parse_stat(line).unwrap();
+
+        assert_eq!((stat.comm.as_str(), stat.ppid, stat.cpu_ticks, stat.start_time, stat.rss_pages), ("Web Content", 1, 286, 5373, 58000));
+    }
+
+    #[test]
+    fn malformed_stat() {
+        assert!(parse_stat("").is_none());
+        assert!(parse_stat("1234 bash S 1").is_none());
+        assert!(parse_stat("1234 (bash) S 1 2 3").is_none());
+        assert!(parse_stat(&stat_line("bash").replace(" 22 ", " x ")).is_none());
+        assert!(parse_stat(")1234 (bash").is_none());
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(process_name("firefox", Some("/usr/lib/firefox/firefox"), None), "firefox");
+        // comm is cut off, and the executable isn't visible without privileges
+        assert_eq!(process_name("CompatTelRunne", None, Some("/opt/CompatTelRunner.exe --x")), "CompatTelRunner.exe");
+        // a login shell's argv[0] doesn't agree with comm
+        assert_eq!(process_name("bash", None, Some("-bash")), "bash");
+        // argv[0] was rewritten to something else
+        assert_eq!(process_name("python3", None, Some("my-daemon --worker")), "python3");
+        assert_eq!(process_name("kworker/0:1", None, None), "kworker/0:1");
+    }
+
+    #[test]
+    fn deleted_executables() {
+        assert_eq!(process_name("updater", Some("/opt/app/updater (deleted)"), None), "updater");
+        assert_eq!(process_name("my (deleted)", Some("/opt/my (deleted) (deleted)"), None), "my (deleted)");
+        assert_eq!(process_name("x", Some("/opt/app/x"), None), "x");
+    }
+}
@@ -0,0 +1,348 @@
+use super::{ProcessEventSource, ProcessInfo, SourceError};
+use crate::{matcher::Pattern, monitor::{Sample, UsageSampler}, threshold::{ProcessKey, Usage}, utils};
+
+use std::{collections::HashMap, error::Error, mem, path::Path, thread::JoinHandle, sync::mpsc, time::Duration};
+
+use async_channel::{bounded, unbounded, Receiver, Sender};
+use futures::{executor::block_on, future::{select, Either}};
+
+use WMI_Query::{
+    event, wql::{prop, Query}, AsyncQueryReceiver, EventKind, IWbemClassObjectWrapper, InstanceEvent, WMIClass, WMIConnection,
+    Win32_Process::Win32_Process, Win32_ProcessTrace::Win32_ProcessStartTrace
+};
+
+
+/// How often WMI checks for new processes, unless told otherwise
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Watches for new processes through a WMI `__InstanceCreationEvent` query,
+/// or `Win32_ProcessStartTrace` events if asked to
+pub struct WmiSource {
+    query: String,
+    /// Try `Win32_ProcessStartTrace` first, and only use `query` if that can't be subscribed to
+    trace: bool,
+    /// Only processes with a name matching one of these are sent. Nothing sends everything.
+    names: Vec<Pattern>,
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+    /// Taken on the connection's thread while starting
+    running: Vec<ProcessInfo>
+}
+
+impl WmiSource {
+    pub fn new() -> Self {
+        Self::with_interval(DEFAULT_INTERVAL)
+    }
+
+    /// Have WMI check for new processes every `interval`
+    pub fn with_interval(interval: Duration) -> Self {
+        Self::with_filter(interval, &[])
+    }
+
+    /// Have WMI check for new processes every `interval`, and only send the ones with a name
+    /// that matches one of `names`. No names sends every process.
+    /// WMI leaves out the rest itself if every pattern can be written as a `LIKE`.
+    pub fn with_filter(interval: Duration, names: &[Pattern]) -> Self {
+        let likes: Vec<String> = names.iter().map(|v| v.to_like()).collect::<Option<_>>().unwrap_or_default();
+        let query = event::event_query(Win32_Process::CLASS, &[EventKind::Created], interval)
+            .and_any(likes.iter().map(|v| prop("TargetInstance.Name").like(v)));
+
+        let mut source = Self::with_query(&query.to_string());
+        source.names = names.to_vec();
+        source
+    }
+
+    /// The query must select instance events whose `TargetInstance` is a `Win32_Process`.
+    /// Only creation events are reported, anything else it selects is skipped.
+    pub fn with_query(query: &str) -> Self {
+        Self {
+            query: query.to_string(),
+            trace: false,
+            names: Vec::new(),
+            stop: None,
+            thread: None,
+            running: Vec::new()
+        }
+    }
+}
+
+impl WmiSource {
+    /// Get told about processes as they start through `Win32_ProcessStartTrace`, instead of polling for them.
+    /// That needs admin, so it falls back to polling if it can't subscribe.
+    pub fn with_trace(mut self) -> Self {
+        self.trace = true;
+        self
+    }
+}
+
+impl Default for WmiSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<Win32_Process> for ProcessInfo {
+    fn from(process: Win32_Process) -> Self {
+        let non_empty = |s: String| if s.is_empty() { None } else { Some(s) };
+
+        Self {
+            name: process.Name,
+            pid: process.ProcessId,
+            parent_pid: process.ParentProcessId,
+            parent_name: None,
+            executable_path: non_empty(process.ExecutablePath),
+            command_line: non_empty(process.CommandLine),
+            session_id: Some(process.SessionId),
+            user: None,
+            start_time: process.CreationDate.map(|v| v.unix_micros() as u64)
+        }
+    }
+}
+
+impl From<Win32_ProcessStartTrace> for ProcessInfo {
+    fn from(trace: Win32_ProcessStartTrace) -> Self {
+        Self {
+            name: trace.ProcessName,
+            pid: trace.ProcessID,
+            parent_pid: trace.ParentProcessID,
+            parent_name: None,
+            executable_path: None,
+            command_line: None,
+            session_id: Some(trace.SessionID),
+            user: None,
+            start_time: None
+        }
+    }
+}
+
+/// Win32_Process doesn't carry the user or the parent's name, so ask the process itself
+fn read_process(process: Win32_Process) -> ProcessInfo {
+    complete(ProcessInfo::from(process))
+}
+
+/// Fill in the user and the parent's name from the processes themselves
+fn complete(mut process: ProcessInfo) -> ProcessInfo {
+    process.user = utils::process_user(process.pid);
+    process.parent_name = utils::process_image_path(process.parent_pid)
+        .and_then(|v| Path::new(&v).file_name().map(|v| v.to_string_lossy().into_owned()));
+
+    process
+}
+
+/// A start trace only has the name and ids, so look up the rest while the process is still there
+fn read_start_trace(wmi_con: &WMIConnection, event: &IWbemClassObjectWrapper) -> Result<ProcessInfo, Box<dyn Error>> {
+    let trace = event.deserialize::<Win32_ProcessStartTrace>()?;
+
+    let query = Query::select_all().from_class::<Win32_Process>().and(prop("ProcessId").eq(trace.ProcessID));
+    let found = wmi_con.exec_query(&query.to_string())
+        .ok()
+        .and_then(|mut rows| rows.next())
+        .and_then(|row| row.ok()?.deserialize::<Win32_Process>().ok());
+
+    if let Some(process) = found {
+        return Ok(read_process(process));
+    }
+
+    // it's already gone, so the path is all that might still be found
+    let mut process = ProcessInfo::from(trace);
+    process.executable_path = utils::process_image_path(process.pid);
+
+    Ok(complete(process))
+}
+
+/// The process an `__InstanceCreationEvent` is about, or nothing for any other instance event
+fn read_instance_event(event: &IWbemClassObjectWrapper) -> Result<Option<ProcessInfo>, Box<dyn Error>> {
+    match event.deserialize_event::<Win32_Process>()? {
+        InstanceEvent::Created(v) => Ok(Some(read_process(v))),
+        _ => Ok(None)
+    }
+}
+
+/// Subscribe to start traces if `trace` is set and they're available, otherwise to `query`.
+/// Also says whether it's the traces. A trace's `ProcessName` is cut off at 15 characters,
+/// so WMI can't tell which names match and every trace is sent.
+fn subscribe<'a>(
+    wmi_con: &'a WMIConnection,
+    query: &str,
+    trace: bool
+) -> Result<(AsyncQueryReceiver<'a>, bool), Box<dyn Error>> {
+    if trace {
+        let traces = Query::select_all().from_class::<Win32_ProcessStartTrace>();
+
+        match wmi_con.exec_notification_query_async(&traces.to_string()) {
+            Ok(v) => return Ok((v, true)),
+            Err(e) => println!("Warning: Can't get Win32_ProcessStartTrace events ({e}), polling instead")
+        }
+    }
+
+    Ok((wmi_con.exec_notification_query_async(query)?, false))
+}
+
+/// Samples usage through `Win32_Process`, looking up the rest of each process only the first time it's seen
+pub struct WmiUsage {
+    connection: WMIConnection,
+    known: HashMap<ProcessKey, ProcessInfo>
+}
+
+impl WmiUsage {
+    /// The connection can't leave the thread it's made on
+    pub fn new() -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            connection: WMIConnection::new()?,
+            known: HashMap::new()
+        })
+    }
+}
+
+impl UsageSampler for WmiUsage {
+    fn sample(&mut self) -> Result<Vec<Sample>, Box<dyn Error>> {
+        let mut samples = Vec::new();
+        let mut known = HashMap::new();
+
+        for process in self.connection.query::<Win32_Process>()? {
+            let key = (process.ProcessId, process.CreationDate.map(|v| v.unix_micros() as u64).unwrap_or_default());
+
+            let usage = Usage {
+                memory: process.WorkingSetSize,
+                cpu_time: process.UserModeTime + process.KernelModeTime
+            };
+
+            let process = match self.known.remove(&key) {
+                Some(v) => v,
+                None => read_process(process)
+            };
+
+            samples.push(Sample {
+                key,
+                process: process.clone(),
+                usage
+            });
+
+            known.insert(key, process);
+        }
+
+        self.known = known;
+
+        Ok(samples)
+    }
+}
+
+impl ProcessEventSource for WmiSource {
+    fn start(&mut self) -> Result<Receiver<ProcessInfo>, Box<dyn Error>> {
+        if self.thread.is_some() {
+            return Err(Box::new(SourceError::AlreadyStarted));
+        }
+
+        let (tx, rx) = unbounded();
+        let (stop_tx, stop_rx) = bounded::<()>(1);
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let query = self.query.clone();
+        let trace = self.trace;
+        let names = self.names.clone();
+
+        // COM objects can't leave the thread they were made on, so the connection
+        // and the query both live on this one and only plain data is sent out
+        let thread = std::thread::spawn(move || {
+            let wmi_con = match WMIConnection::new() {
+                Ok(v) => v,
+                Err(e) => {
+                    let _ = ready_tx.send(Err(e.to_string()));
+                    return;
+                }
+            };
+
+            let (events, traced) = match subscribe(&wmi_con, &query, trace) {
+                Ok(v) => v,
+                Err(e) => {
+                    let _ = ready_tx.send(Err(e.to_string()));
+                    return;
+                }
+            };
+
+            // only after subscribing, so nothing is missed in between
+            let running = match wmi_con.query::<Win32_Process>() {
+                Ok(v) => v.into_iter().map(read_process).collect(),
+                Err(e) => {
+                    let _ = ready_tx.send(Err(e.to_string()));
+                    return;
+                }
+            };
+
+            let _ = ready_tx.send(Ok(running));
+
+            loop {
+                let next = select(Box::pin(events.recv()), Box::pin(stop_rx.recv()));
+
+                match block_on(next) {
+                    Either::Left((Ok(Ok(event)), _)) => {
+                        let process = if traced {
+                            read_start_trace(&wmi_con, &event).map(Some)
+                        } else {
+                            read_instance_event(&event)
+                        };
+
+                        let process = match process {
+                            Ok(Some(v)) => v,
+                            // only new processes are reported
+                            Ok(None) => continue,
+                            Err(e) => {
+                                println!("Warning: Failed to read event: {e}");
+                                continue;
+                            }
+                        };
+
+                        // traces aren't filtered by WMI, and neither is anything `query` couldn't filter
+                        if !names.is_empty() && !names.iter().any(|v| v.matches(&process.name)) {
+                            continue;
+                        }
+
+                        if tx.try_send(process).is_err() {
+                            break;
+                        }
+                    }
+
+                    Either::Left((Ok(Err(e)), _)) => println!("Warning: WMI query error: {e}"),
+
+                    // query finished, or we were told to stop
+                    Either::Left((Err(_), _)) | Either::Right(_) => break
+                }
+            }
+
+            tx.close();
+        });
+
+        match ready_rx.recv() {
+            Ok(Ok(running)) => self.running = running,
+            Ok(Err(e)) => {
+                let _ = thread.join();
+                return Err(Box::new(SourceError::StartFailed(e)));
+            }
+            Err(e) => return Err(Box::new(SourceError::StartFailed(e.to_string())))
+        }
+
+        self.stop = Some(stop_tx);
+        self.thread = Some(thread);
+
+        Ok(rx)
+    }
+
+    fn stop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.try_send(());
+        }
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+
+    fn running(&mut self) -> Result<Vec<ProcessInfo>, Box<dyn Error>> {
+        Ok(mem::take(&mut self.running))
+    }
+}
+
+impl Drop for WmiSource {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
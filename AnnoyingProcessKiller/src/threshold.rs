@@ -0,0 +1,350 @@
+use std::{collections::HashMap, time::{Duration, Instant}};
+
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+
+/// Resource limits a process has to stay over for a while before a rule acts on it.
+/// Every limit that is set has to be exceeded.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Threshold {
+    /// Working set in bytes, or a size like `"1GiB"` or `"500MB"`
+    #[serde(default, skip_serializing_if = "Option::is_none", deserialize_with = "deserialize_size")]
+    pub memory: Option<u64>,
+
+    /// Percent of all CPUs together, like Task Manager shows it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu: Option<f64>,
+
+    /// Seconds it has to stay over before the rule acts
+    #[serde(default, rename = "for")]
+    pub duration: f64
+}
+
+impl Threshold {
+    /// Why this threshold can't be used, if it can't
+    pub fn problem(&self) -> Option<&'static str> {
+        if self.memory.is_none() && self.cpu.is_none() {
+            return Some("sets neither `memory` nor `cpu`");
+        }
+
+        if self.cpu.is_some_and(|v| !v.is_finite()) {
+            return Some("has a `cpu` that isn't a finite number");
+        }
+
+        if self.cpu.is_some_and(|v| v < 0.0) {
+            return Some("has a negative `cpu`");
+        }
+
+        if !self.duration.is_finite() {
+            return Some("has a `for` that isn't a finite number");
+        }
+
+        if self.duration < 0.0 {
+            return Some("has a negative `for`");
+        }
+
+        // too long to wait for anyway, and it'd otherwise be no wait at all
+        if Duration::try_from_secs_f64(self.duration).is_err() {
+            return Some("has a `for` that's too long");
+        }
+
+        None
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::try_from_secs_f64(self.duration).unwrap_or_default()
+    }
+}
+
+/// Accepts plain bytes, or a number followed by B, KB, MB, GB, KiB, MiB or GiB
+fn deserialize_size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(u64),
+        Text(String)
+    }
+
+    let text = match Size::deserialize(deserializer)? {
+        Size::Bytes(v) => return Ok(Some(v)),
+        Size::Text(v) => v
+    };
+
+    let split = text.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" => 1000,
+        "mb" => 1000 * 1000,
+        "gb" => 1000 * 1000 * 1000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        _ => return Err(de::Error::custom(format!("unknown size unit in `{text}`")))
+    };
+
+    let number: f64 = number.parse().map_err(|_| de::Error::custom(format!("invalid size `{text}`")))?;
+
+    Ok(Some((number * multiplier as f64) as u64))
+}
+
+/// How much a process is using at one point in time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Usage {
+    /// Working set, or resident memory on Linux, in bytes
+    pub memory: u64,
+
+    /// CPU time used since it started, user and kernel together
+    pub cpu_time: Duration
+}
+
+/// Tells one run of a process from another that later gets the same pid
+pub type ProcessKey = (u32, u64);
+
+#[derive(Debug, Default)]
+struct State {
+    /// The last sample, to work out CPU usage from
+    last: Option<(Instant, Duration)>,
+    /// When it went over and has stayed over since
+    over_since: Option<Instant>,
+    /// Already acted on for this stretch of being over
+    fired: bool
+}
+
+/// Follows each process' usage against each threshold it's watched for.
+/// It only knows the time it's given, so it can be driven with a made up clock.
+#[derive(Debug)]
+pub struct Tracker {
+    cpus: u32,
+    states: HashMap<(usize, ProcessKey), State>
+}
+
+impl Tracker {
+    /// `cpus` is how many CPUs 100% is spread over
+    pub fn new(cpus: u32) -> Self {
+        Self {
+            cpus: cpus.max(1),
+            states: HashMap::new()
+        }
+    }
+
+    /// Record a sample taken at `now` of process `key` against threshold number `rule`.
+    /// True once it has been over for the threshold's whole duration, then not again
+    /// until it has gone back under and over again.
+    pub fn update(&mut self, now: Instant, rule: usize, threshold: &Threshold, key: ProcessKey, usage: Usage) -> bool {
+        let state = self.states.entry((rule, key)).or_default();
+
+        // CPU usage needs two samples to work out
+        let cpu = state.last.and_then(|(then, cpu_time)| {
+            let elapsed = now.checked_duration_since(then)?.as_secs_f64();
+            if elapsed <= 0.0 {
+                return None;
+            }
+
+            let used = usage.cpu_time.checked_sub(cpu_time)?.as_secs_f64();
+            Some(used / elapsed / self.cpus as f64 * 100.0)
+        });
+
+        state.last = Some((now, usage.cpu_time));
+
+        let over = threshold.memory.is_none_or(|limit| usage.memory > limit)
+            && threshold.cpu.is_none_or(|limit| cpu.is_some_and(|v| v > limit));
+
+        if !over {
+            state.over_since = None;
+            state.fired = false;
+            return false;
+        }
+
+        let since = *state.over_since.get_or_insert(now);
+        if state.fired || now.duration_since(since) < threshold.duration() {
+            return false;
+        }
+
+        state.fired = true;
+        true
+    }
+
+    /// Forget every process that isn't in `alive` anymore
+    pub fn retain(&mut self, alive: impl Fn(&ProcessKey) -> bool) {
+        self.states.retain(|(_, key), _| alive(key));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: ProcessKey = (42, 1000);
+    const MIB: u64 = 1 << 20;
+
+    fn memory(limit: u64, duration: f64) -> Threshold {
+        Threshold { memory: Some(limit), duration, ..Default::default() }
+    }
+
+    fn cpu(limit: f64, duration: f64) -> Threshold {
+        Threshold { cpu: Some(limit), duration, ..Default::default() }
+    }
+
+    fn using(memory: u64) -> Usage {
+        Usage { memory, cpu_time: Duration::ZERO }
+    }
+
+    fn cpu_time(secs: f64) -> Usage {
+        Usage { memory: 0, cpu_time: Duration::from_secs_f64(secs) }
+    }
+
+    fn at(start: Instant, secs: u64) -> Instant {
+        start + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn fires_after_staying_over() {
+        let (mut tracker, start, threshold) = (Tracker::new(1), Instant::now(), memory(100 * MIB, 10.0));
+
+        assert!(!tracker.update(at(start, 0), 0, &threshold, KEY, using(200 * MIB)));
+        assert!(!tracker.update(at(start, 5), 0, &threshold, KEY, using(200 * MIB)));
+        assert!(tracker.update(at(start, 10), 0, &threshold, KEY, using(200 * MIB)));
+    }
+
+    #[test]
+    fn fires_right_away_without_a_duration() {
+        let mut tracker = Tracker::new(1);
+        assert!(tracker.update(Instant::now(), 0, &memory(100, 0.0), KEY, using(101)));
+    }
+
+    #[test]
+    fn has_to_be_over_not_at() {
+        let mut tracker = Tracker::new(1);
+        assert!(!tracker.update(Instant::now(), 0, &memory(100, 0.0), KEY, using(100)));
+    }
+
+    #[test]
+    fn fires_once() {
+        let (mut tracker, start, threshold) = (Tracker::new(1), Instant::now(), memory(100, 5.0));
+
+        let fired: Vec<bool> = (0..20)
+            .map(|v| tracker.update(at(start, v), 0, &threshold, KEY, using(200)))
+            .collect();
+
+        assert_eq!(fired.iter().filter(|&&v| v).count(), 1);
+        assert!(fired[5]);
+    }
+
+    #[test]
+    fn going_under_starts_over() {
+        let (mut tracker, start, threshold) = (Tracker::new(1), Instant::now(), memory(100, 5.0));
+
+        assert!(!tracker.update(at(start, 0), 0, &threshold, KEY, using(200)));
+        assert!(!tracker.update(at(start, 4), 0, &threshold, KEY, using(50)));
+        assert!(!tracker.update(at(start, 5), 0, &threshold, KEY, using(200)));
+        assert!(!tracker.update(at(start, 9), 0, &threshold, KEY, using(200)));
+        assert!(tracker.update(at(start, 10), 0, &threshold, KEY, using(200)));
+    }
+
+    #[test]
+    fn rearms_after_going_under() {
+        let (mut tracker, start, threshold) = (Tracker::new(1), Instant::now(), memory(100, 2.0));
+
+        assert!(!tracker.update(at(start, 0), 0, &threshold, KEY, using(200)));
+        assert!(tracker.update(at(start, 2), 0, &threshold, KEY, using(200)));
+        assert!(!tracker.update(at(start, 3), 0, &threshold, KEY, using(200)));
+
+        assert!(!tracker.update(at(start, 4), 0, &threshold, KEY, using(50)));
+
+        assert!(!tracker.update(at(start, 5), 0, &threshold, KEY, using(200)));
+        assert!(tracker.update(at(start, 7), 0, &threshold, KEY, using(200)));
+    }
+
+    #[test]
+    fn cpu_needs_two_samples() {
+        let (mut tracker, start, threshold) = (Tracker::new(1), Instant::now(), cpu(50.0, 0.0));
+
+        // however much it has used, there's nothing to compare the first sample to
+        assert!(!tracker.update(at(start, 0), 0, &threshold, KEY, cpu_time(100.0)));
+        assert!(tracker.update(at(start, 1), 0, &threshold, KEY, cpu_time(100.9)));
+    }
+
+    #[test]
+    fn cpu_is_spread_over_every_cpu() {
+        let (mut tracker, start, threshold) = (Tracker::new(4), Instant::now(), cpu(50.0, 0.0));
+
+        // 1.5 seconds a second is 37.5% of 4 CPUs
+        assert!(!tracker.update(at(start, 0), 0, &threshold, KEY, cpu_time(0.0)));
+        assert!(!tracker.update(at(start, 1), 0, &threshold, KEY, cpu_time(1.5)));
+        assert!(tracker.update(at(start, 2), 0, &threshold, KEY, cpu_time(4.0)));
+    }
+
+    #[test]
+    fn every_limit_has_to_be_over() {
+        let (mut tracker, start) = (Tracker::new(1), Instant::now());
+        let threshold = Threshold { memory: Some(100), cpu: Some(50.0), duration: 0.0 };
+
+        assert!(!tracker.update(at(start, 0), 0, &threshold, KEY, Usage { memory: 200, cpu_time: Duration::ZERO }));
+        assert!(!tracker.update(at(start, 1), 0, &threshold, KEY, Usage { memory: 200, cpu_time: Duration::from_millis(100) }));
+        assert!(!tracker.update(at(start, 2), 0, &threshold, KEY, Usage { memory: 50, cpu_time: Duration::from_secs(1) }));
+        assert!(tracker.update(at(start, 3), 0, &threshold, KEY, Usage { memory: 200, cpu_time: Duration::from_secs(2) }));
+    }
+
+    #[test]
+    fn rules_and_processes_are_tracked_apart() {
+        let (mut tracker, start, threshold) = (Tracker::new(1), Instant::now(), memory(100, 5.0));
+        let reused = (KEY.0, KEY.1 + 1);
+
+        assert!(!tracker.update(at(start, 0), 0, &threshold, KEY, using(200)));
+        assert!(!tracker.update(at(start, 3), 1, &threshold, KEY, using(200)));
+        assert!(!tracker.update(at(start, 3), 0, &threshold, reused, using(200)));
+
+        assert!(tracker.update(at(start, 5), 0, &threshold, KEY, using(200)));
+        assert!(!tracker.update(at(start, 5), 1, &threshold, KEY, using(200)));
+        assert!(!tracker.update(at(start, 5), 0, &threshold, reused, using(200)));
+    }
+
+    #[test]
+    fn retain_forgets_the_dead() {
+        let (mut tracker, start, threshold) = (Tracker::new(1), Instant::now(), memory(100, 5.0));
+        let other = (7, 1);
+
+        assert!(!tracker.update(at(start, 0), 0, &threshold, KEY, using(200)));
+        assert!(!tracker.update(at(start, 0), 0, &threshold, other, using(200)));
+
+        tracker.retain(|key| *key == other);
+        assert_eq!(tracker.states.len(), 1);
+
+        // KEY starts over, other carries on
+        assert!(!tracker.update(at(start, 5), 0, &threshold, KEY, using(200)));
+        assert!(tracker.update(at(start, 5), 0, &threshold, other, using(200)));
+    }
+
+    #[test]
+    fn sizes() {
+        let size = |v: &str| serde_json::from_str::<Threshold>(&format!(r#"{{"memory": {v}}}"#)).map(|v| v.memory.unwrap());
+
+        assert_eq!(size("1024").unwrap(), 1024);
+        assert_eq!(size(r#""500MB""#).unwrap(), 500_000_000);
+        assert_eq!(size(r#""1.5 GiB""#).unwrap(), 3 << 29);
+        assert_eq!(size(r#""2kib""#).unwrap(), 2048);
+        assert!(size(r#""5 TB""#).is_err());
+        assert!(size(r#""lots""#).is_err());
+    }
+
+    #[test]
+    fn problems() {
+        assert_eq!(Threshold::default().problem(), Some("sets neither `memory` nor `cpu`"));
+        assert_eq!(cpu(-1.0, 0.0).problem(), Some("has a negative `cpu`"));
+        assert_eq!(memory(1, -1.0).problem(), Some("has a negative `for`"));
+        assert_eq!(memory(1, 1e300).problem(), Some("has a `for` that's too long"));
+        assert_eq!(cpu(10.0, 5.0).problem(), None);
+        assert_eq!(memory(1, 0.0).problem(), None);
+    }
+
+    #[test]
+    fn non_finite_problems() {
+        for v in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert_eq!(cpu(v, 0.0).problem(), Some("has a `cpu` that isn't a finite number"), "{v}");
+            assert_eq!(memory(1, v).problem(), Some("has a `for` that isn't a finite number"), "{v}");
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use super::ProcessError;
+use crate::{actions::PriorityClass, source::{procfs, ProcessInfo}};
+
+use std::{fs, io, mem};
+
+
+fn errno() -> u32 {
+    io::Error::last_os_error().raw_os_error().unwrap_or_default() as u32
+}
+
+fn send_signal(pid: u32, signal: i32) -> Result<(), u32> {
+    let res = unsafe { libc::kill(pid as libc::pid_t, signal) };
+    if res != 0 {
+        return Err(errno());
+    }
+
+    Ok(())
+}
+
+/// Priority and affinity belong to each thread rather than the process
+fn threads(pid: u32) -> Vec<u32> {
+    let tids: Vec<u32> = fs::read_dir(format!("/proc/{pid}/task"))
+        .map(|dir| {
+            dir.filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    if tids.is_empty() {
+        vec![pid]
+    } else {
+        tids
+    }
+}
+
+/// Every running process, with just its name, pid, parent pid and start time filled in
+pub fn process_table() -> Vec<ProcessInfo> {
+    procfs::scan()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(pid, stat)| ProcessInfo {
+            name: stat.comm,
+            pid,
+            parent_pid: stat.ppid,
+            start_time: Some(stat.start_time),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Linux has no exit codes for killed processes, so `exit_code` is ignored
+pub fn kill_process(name: &str, pid: u32, _exit_code: u32) -> Result<(), ProcessError> {
+    send_signal(pid, libc::SIGKILL).map_err(|errcode| ProcessError::TerminationFailed {
+        process: name.to_string(),
+        pid,
+        errcode
+    })
+}
+
+pub fn suspend_process(name: &str, pid: u32) -> Result<(), ProcessError> {
+    send_signal(pid, libc::SIGSTOP).map_err(|errcode| ProcessError::SuspendFailed {
+        process: name.to_string(),
+        pid,
+        errcode
+    })
+}
+
+/// Priority classes are mapped onto nice values
+pub fn set_priority(name: &str, pid: u32, class: PriorityClass) -> Result<(), ProcessError> {
+    let nice = match class {
+        PriorityClass::Idle => 19,
+        PriorityClass::BelowNormal => 10,
+        PriorityClass::Normal => 0,
+        PriorityClass::AboveNormal => -5,
+        PriorityClass::High => -10,
+        PriorityClass::Realtime => -20
+    };
+
+    for tid in threads(pid) {
+        let res = unsafe { libc::setpriority(libc::PRIO_PROCESS, tid as libc::id_t, nice) };
+        if res != 0 {
+            return Err(ProcessError::SetPriorityFailed {
+                process: name.to_string(),
+                pid,
+                errcode: errno()
+            });
+        }
+    }
+
+    Ok(())
+}
+
+pub fn set_affinity(name: &str, pid: u32, cpus: &[usize]) -> Result<(), ProcessError> {
+    let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+    let mut any = false;
+
+    for &cpu in cpus.iter().filter(|&&cpu| cpu < libc::CPU_SETSIZE as usize) {
+        unsafe { libc::CPU_SET(cpu, &mut set) };
+        any = true;
+    }
+
+    if !any {
+        return Err(ProcessError::EmptyAffinity {
+            process: name.to_string(),
+            pid
+        });
+    }
+
+    for tid in threads(pid) {
+        let res = unsafe {
+            libc::sched_setaffinity(tid as libc::pid_t, mem::size_of::<libc::cpu_set_t>(), &set)
+        };
+
+        if res != 0 {
+            return Err(ProcessError::SetAffinityFailed {
+                process: name.to_string(),
+                pid,
+                errcode: errno()
+            });
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,85 @@
+#[cfg(windows)]
+mod windows;
+#[cfg(windows)]
+pub use self::windows::*;
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "linux")]
+pub use linux::*;
+
+use thiserror::Error;
+
+
+#[derive(Error, Debug)]
+pub enum ProcessError {
+    #[error("Process termination failed -> {process} : {pid}) -> code: {errcode}")]
+    TerminationFailed {
+        process: String,
+        pid: u32,
+        errcode: u32
+    },
+
+    #[error("Process suspension failed -> {process} : {pid} -> code: {errcode}")]
+    SuspendFailed {
+        process: String,
+        pid: u32,
+        errcode: u32
+    },
+
+    #[error("Setting priority failed -> {process} : {pid} -> code: {errcode}")]
+    SetPriorityFailed {
+        process: String,
+        pid: u32,
+        errcode: u32
+    },
+
+    #[error("Setting affinity failed -> {process} : {pid} -> code: {errcode}")]
+    SetAffinityFailed {
+        process: String,
+        pid: u32,
+        errcode: u32
+    },
+
+    #[error("None of the CPUs in the affinity exist -> {process} : {pid}")]
+    EmptyAffinity {
+        process: String,
+        pid: u32
+    },
+
+    #[cfg(windows)]
+    #[error("HANDLE is NULL -> {process} : {pid}) -> code: {errcode}")]
+    NullHandle {
+        process: String,
+        pid: u32,
+        errcode: u32
+    },
+
+    #[cfg(windows)]
+    #[error("Failed to close HANDLE -> {process} : {pid}) -> code: {errcode}")]
+    CloseHandleFailed {
+        process: String,
+        pid: u32,
+        errcode: u32
+    },
+
+    #[cfg(windows)]
+    #[error("Failed to open process token")]
+    OpenProcessTokenFailed {
+        errcode: u32
+    },
+
+    #[cfg(windows)]
+    #[error("Failed to lookup privilege")]
+    PrivilegeLookupFailed {
+        name: String,
+        errcode: u32
+    },
+
+    #[cfg(windows)]
+    #[error("Adjust token privilege failed")]
+    AdjustTokenPrivilegeFailed {
+        name: String,
+        errcode: u32
+    }
+}
@@ -0,0 +1,457 @@
+use windows::{
+    Win32::{
+        System::{
+            Console::{
+                AttachConsole, FreeConsole, ATTACH_PARENT_PROCESS
+            },
+            Threading::{
+                TerminateProcess, OpenProcess, PROCESS_TERMINATE, PROCESS_QUERY_INFORMATION, OpenProcessToken,
+                PROCESS_QUERY_LIMITED_INFORMATION, QueryFullProcessImageNameW, PROCESS_NAME_WIN32,
+                PROCESS_SET_INFORMATION, PROCESS_ACCESS_RIGHTS, SetPriorityClass, SetProcessAffinityMask,
+                OpenThread, SuspendThread, THREAD_SUSPEND_RESUME,
+                IDLE_PRIORITY_CLASS, BELOW_NORMAL_PRIORITY_CLASS, NORMAL_PRIORITY_CLASS,
+                ABOVE_NORMAL_PRIORITY_CLASS, HIGH_PRIORITY_CLASS, REALTIME_PRIORITY_CLASS, GetProcessTimes
+            },
+            Diagnostics::ToolHelp::{
+                CreateToolhelp32Snapshot, Thread32First, Thread32Next, THREADENTRY32, TH32CS_SNAPTHREAD,
+                Process32FirstW, Process32NextW, PROCESSENTRY32W, TH32CS_SNAPPROCESS
+            }
+        },
+        Security::{
+            TOKEN_ADJUST_PRIVILEGES, LookupPrivilegeValueA, TOKEN_PRIVILEGES,
+            SE_PRIVILEGE_ENABLED, AdjustTokenPrivileges, TOKEN_PRIVILEGES_ATTRIBUTES,
+            TOKEN_QUERY, GetTokenInformation, TokenUser, TOKEN_USER, LookupAccountSidW, SID_NAME_USE
+        },
+        Foundation::{
+            GetLastError, CloseHandle, HANDLE, LUID
+        }
+    },
+    core::{PCSTR, PCWSTR, PWSTR}
+};
+
+use super::ProcessError;
+use crate::{actions::PriorityClass, source::ProcessInfo};
+
+use std::ffi::CString;
+use std::error::Error;
+
+
+pub fn set_privilege(name: &str, state: bool) -> Result<(), Box<dyn Error>> {
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_INFORMATION, false, std::process::id());
+        if handle.is_invalid() {
+            return Err(Box::new(ProcessError::NullHandle {
+                process: env!("CARGO_PKG_NAME").to_string(),
+                pid: std::process::id(),
+                errcode: GetLastError().0
+            }));
+        }
+
+        let mut token_handle = HANDLE(0);
+        let res: bool = OpenProcessToken(
+            handle,
+            TOKEN_ADJUST_PRIVILEGES,
+            &mut token_handle as *mut _
+        ).into();
+
+        if !res {
+            return Err(Box::new(ProcessError::OpenProcessTokenFailed {
+                errcode: GetLastError().0
+            }));
+        }
+
+        let mut luid = LUID::default();
+        let privilege = CString::new(name)?;
+        let res: bool = LookupPrivilegeValueA(
+            PCSTR::default(),
+            PCSTR(privilege.as_ptr() as *const _),
+            &mut luid as *mut _
+        ).into();
+
+        if !res {
+            return Err(Box::new(ProcessError::PrivilegeLookupFailed {
+                name: name.to_string(),
+                errcode: GetLastError().0
+            }));
+        }
+
+        let mut tp = TOKEN_PRIVILEGES::default();
+        tp.PrivilegeCount = 1;
+        tp.Privileges[0].Luid = luid;
+
+        if state {
+            tp.Privileges[0].Attributes = SE_PRIVILEGE_ENABLED;
+        } else {
+            tp.Privileges[0].Attributes = TOKEN_PRIVILEGES_ATTRIBUTES(0u32);
+        }
+
+        let res: bool = AdjustTokenPrivileges(
+            token_handle,
+            false,
+            &tp as *const _,
+            std::mem::size_of::<TOKEN_PRIVILEGES>() as u32,
+            std::ptr::null_mut() as *mut _,
+            std::ptr::null_mut() as *mut _
+        ).into();
+
+        if !res {
+            return Err(Box::new(ProcessError::AdjustTokenPrivilegeFailed {
+                name: name.to_string(),
+                errcode: GetLastError().0
+            }));
+        }
+
+        let res: bool = CloseHandle(handle).into();
+        if !res {
+            return Err(Box::new(ProcessError::CloseHandleFailed {
+                process: env!("CARGO_PKG_NAME").to_string(),
+                pid: std::process::id(),
+                errcode: GetLastError().0
+            }));
+        }
+
+        let res: bool = CloseHandle(token_handle).into();
+        if !res {
+            return Err(Box::new(ProcessError::CloseHandleFailed {
+                process: env!("CARGO_PKG_NAME").to_string(),
+                pid: std::process::id(),
+                errcode: GetLastError().0
+            }));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn hide_console() {
+    unsafe {
+        FreeConsole();
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+pub fn kill_process(name: &str, pid: u32, exit_code: u32) -> Result<(), ProcessError> {
+    unsafe {
+        let handle = OpenProcess(PROCESS_TERMINATE, false, pid);
+        if handle.is_invalid() {
+            return Err(ProcessError::NullHandle {
+                process: name.to_string(),
+                pid,
+                errcode: GetLastError().0
+            });
+        }
+
+        let res: bool = TerminateProcess(handle, exit_code).into();
+        if !res {
+            return Err(ProcessError::TerminationFailed {
+                process: name.to_string(),
+                pid,
+                errcode: GetLastError().0
+            });
+        }
+
+        let res: bool = CloseHandle(handle).into();
+        if !res {
+            return Err(ProcessError::CloseHandleFailed {
+                process: name.to_string(),
+                pid,
+                errcode: GetLastError().0
+            });
+        }
+    }
+
+    Ok(())
+}
+
+fn open_process(name: &str, pid: u32, access: PROCESS_ACCESS_RIGHTS) -> Result<HANDLE, ProcessError> {
+    unsafe {
+        let handle = OpenProcess(access, false, pid);
+        if handle.is_invalid() {
+            return Err(ProcessError::NullHandle {
+                process: name.to_string(),
+                pid,
+                errcode: GetLastError().0
+            });
+        }
+
+        Ok(handle)
+    }
+}
+
+fn close_handle(name: &str, pid: u32, handle: HANDLE) -> Result<(), ProcessError> {
+    unsafe {
+        let res: bool = CloseHandle(handle).into();
+        if !res {
+            return Err(ProcessError::CloseHandleFailed {
+                process: name.to_string(),
+                pid,
+                errcode: GetLastError().0
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// There's no documented way to suspend a whole process, so suspend each of its threads
+pub fn suspend_process(name: &str, pid: u32) -> Result<(), ProcessError> {
+    unsafe {
+        let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0);
+        if snapshot.is_invalid() {
+            return Err(ProcessError::SuspendFailed {
+                process: name.to_string(),
+                pid,
+                errcode: GetLastError().0
+            });
+        }
+
+        let mut entry = THREADENTRY32 {
+            dwSize: std::mem::size_of::<THREADENTRY32>() as u32,
+            ..Default::default()
+        };
+
+        let mut result = Ok(());
+        let mut more: bool = Thread32First(snapshot, &mut entry as *mut _).into();
+        while more {
+            if entry.th32OwnerProcessID == pid {
+                let thread = OpenThread(THREAD_SUSPEND_RESUME, false, entry.th32ThreadID);
+                if thread.is_invalid() || SuspendThread(thread) == u32::MAX {
+                    result = Err(ProcessError::SuspendFailed {
+                        process: name.to_string(),
+                        pid,
+                        errcode: GetLastError().0
+                    });
+                }
+
+                if !thread.is_invalid() {
+                    CloseHandle(thread);
+                }
+
+                if result.is_err() {
+                    break;
+                }
+            }
+
+            more = Thread32Next(snapshot, &mut entry as *mut _).into();
+        }
+
+        CloseHandle(snapshot);
+
+        result
+    }
+}
+
+pub fn set_priority(name: &str, pid: u32, class: PriorityClass) -> Result<(), ProcessError> {
+    let class = match class {
+        PriorityClass::Idle => IDLE_PRIORITY_CLASS,
+        PriorityClass::BelowNormal => BELOW_NORMAL_PRIORITY_CLASS,
+        PriorityClass::Normal => NORMAL_PRIORITY_CLASS,
+        PriorityClass::AboveNormal => ABOVE_NORMAL_PRIORITY_CLASS,
+        PriorityClass::High => HIGH_PRIORITY_CLASS,
+        PriorityClass::Realtime => REALTIME_PRIORITY_CLASS
+    };
+
+    let handle = open_process(name, pid, PROCESS_SET_INFORMATION)?;
+
+    unsafe {
+        let res: bool = SetPriorityClass(handle, class).into();
+        if !res {
+            let errcode = GetLastError().0;
+            CloseHandle(handle);
+            return Err(ProcessError::SetPriorityFailed {
+                process: name.to_string(),
+                pid,
+                errcode
+            });
+        }
+    }
+
+    close_handle(name, pid, handle)
+}
+
+pub fn set_affinity(name: &str, pid: u32, cpus: &[usize]) -> Result<(), ProcessError> {
+    let mask = cpus.iter()
+        .filter(|&&cpu| cpu < usize::BITS as usize)
+        .fold(0usize, |mask, cpu| mask | 1 << cpu);
+
+    if mask == 0 {
+        return Err(ProcessError::EmptyAffinity {
+            process: name.to_string(),
+            pid
+        });
+    }
+
+    let handle = open_process(name, pid, PROCESS_SET_INFORMATION)?;
+
+    unsafe {
+        let res: bool = SetProcessAffinityMask(handle, mask).into();
+        if !res {
+            let errcode = GetLastError().0;
+            CloseHandle(handle);
+            return Err(ProcessError::SetAffinityFailed {
+                process: name.to_string(),
+                pid,
+                errcode
+            });
+        }
+    }
+
+    close_handle(name, pid, handle)
+}
+
+/// Every running process, with just its name, pid, parent pid and start time filled in
+pub fn process_table() -> Vec<ProcessInfo> {
+    let mut processes = Vec::new();
+
+    unsafe {
+        let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0);
+        if snapshot.is_invalid() {
+            return processes;
+        }
+
+        let mut entry = PROCESSENTRY32W {
+            dwSize: std::mem::size_of::<PROCESSENTRY32W>() as u32,
+            ..Default::default()
+        };
+
+        let mut more: bool = Process32FirstW(snapshot, &mut entry as *mut _).into();
+        while more {
+            let len = entry.szExeFile.iter().position(|&c| c == 0).unwrap_or(entry.szExeFile.len());
+
+            processes.push(ProcessInfo {
+                name: String::from_utf16_lossy(&entry.szExeFile[..len]),
+                pid: entry.th32ProcessID,
+                parent_pid: entry.th32ParentProcessID,
+                start_time: creation_time(entry.th32ProcessID),
+                ..Default::default()
+            });
+
+            more = Process32NextW(snapshot, &mut entry as *mut _).into();
+        }
+
+        CloseHandle(snapshot);
+    }
+
+    processes
+}
+
+/// When `pid` was started, in microseconds since 1970, if we're allowed to look
+fn creation_time(pid: u32) -> Option<u64> {
+    // between 1601, where FILETIMEs start, and 1970
+    const EPOCH_DIFFERENCE_MICROS: u64 = 11_644_473_600_000_000;
+
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid);
+        if handle.is_invalid() {
+            return None;
+        }
+
+        let (mut creation, mut exit, mut kernel, mut user) = Default::default();
+        let res: bool = GetProcessTimes(handle, &mut creation, &mut exit, &mut kernel, &mut user).into();
+
+        CloseHandle(handle);
+
+        if !res {
+            return None;
+        }
+
+        // FILETIMEs count 100ns
+        let ticks = (creation.dwHighDateTime as u64) << 32 | creation.dwLowDateTime as u64;
+        Some((ticks / 10).saturating_sub(EPOCH_DIFFERENCE_MICROS))
+    }
+}
+
+/// Full path of the executable behind `pid`, if we're allowed to look
+pub fn process_image_path(pid: u32) -> Option<String> {
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid);
+        if handle.is_invalid() {
+            return None;
+        }
+
+        let mut buf = [0u16; 1024];
+        let mut len = buf.len() as u32;
+        let res: bool = QueryFullProcessImageNameW(
+            handle,
+            PROCESS_NAME_WIN32,
+            PWSTR(buf.as_mut_ptr()),
+            &mut len as *mut _
+        ).into();
+
+        CloseHandle(handle);
+
+        if !res {
+            return None;
+        }
+
+        Some(String::from_utf16_lossy(&buf[..len as usize]))
+    }
+}
+
+/// `DOMAIN\user` that owns `pid`, if we're allowed to look
+pub fn process_user(pid: u32) -> Option<String> {
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid);
+        if handle.is_invalid() {
+            return None;
+        }
+
+        let mut token_handle = HANDLE(0);
+        let res: bool = OpenProcessToken(
+            handle,
+            TOKEN_QUERY,
+            &mut token_handle as *mut _
+        ).into();
+
+        CloseHandle(handle);
+
+        if !res {
+            return None;
+        }
+
+        // TOKEN_USER is followed by the SID it points to, so it needs more room than its own size
+        let mut buf = [0u64; 64];
+        let mut len = 0u32;
+        let res: bool = GetTokenInformation(
+            token_handle,
+            TokenUser,
+            buf.as_mut_ptr() as *mut _,
+            std::mem::size_of_val(&buf) as u32,
+            &mut len as *mut _
+        ).into();
+
+        CloseHandle(token_handle);
+
+        if !res {
+            return None;
+        }
+
+        let token_user = &*(buf.as_ptr() as *const TOKEN_USER);
+
+        let mut name = [0u16; 256];
+        let mut name_len = name.len() as u32;
+        let mut domain = [0u16; 256];
+        let mut domain_len = domain.len() as u32;
+        let mut sid_type = SID_NAME_USE::default();
+
+        let res: bool = LookupAccountSidW(
+            PCWSTR::default(),
+            token_user.User.Sid,
+            PWSTR(name.as_mut_ptr()),
+            &mut name_len as *mut _,
+            PWSTR(domain.as_mut_ptr()),
+            &mut domain_len as *mut _,
+            &mut sid_type as *mut _
+        ).into();
+
+        if !res {
+            return None;
+        }
+
+        Some(format!(
+            "{}\\{}",
+            String::from_utf16_lossy(&domain[..domain_len as usize]),
+            String::from_utf16_lossy(&name[..name_len as usize])
+        ))
+    }
+}
@@ -1,49 +1,30 @@
-use std::{error::Error, ffi::c_void, collections::{HashMap, VecDeque}};
+use std::{error::Error, ffi::c_void, collections::{BTreeMap, HashMap, VecDeque}};
+
+use serde::de::DeserializeOwned;
 
 use windows::{
     Win32::{
         System::{
             Wmi::{
-                IWbemClassObject, WBEM_FLAG_ALWAYS, CIM_OBJECT
+                IWbemClassObject, WBEM_FLAG_ALWAYS, CIM_OBJECT, CIM_DATETIME, CIM_REFERENCE, CIM_FLAG_ARRAY,
+                CIMTYPE_ENUMERATION, CIM_SINT8, CIM_UINT16, CIM_UINT32, CIM_SINT64, CIM_UINT64
             },
             Com::{VARIANT, SAFEARRAY},
             Ole::{
-                SafeArrayAccessData, SafeArrayUnaccessData, VT_UNKNOWN, VARENUM,
-                VT_BSTR, VT_I8, VT_EMPTY, VT_I1, VT_I2, VT_I4, VT_BOOL, VT_SAFEARRAY, VT_NULL,
-                VT_UI1, VT_UI2, VT_UI4, VT_UI8, VT_INT, VT_UINT, VT_VOID, VT_R4, VT_R8
+                SafeArrayAccessData, SafeArrayUnaccessData, SafeArrayGetLBound, SafeArrayGetUBound, VariantClear,
+                VT_UNKNOWN, VARENUM, VT_ARRAY, VT_TYPEMASK,
+                VT_BSTR, VT_I8, VT_EMPTY, VT_I1, VT_I2, VT_I4, VT_BOOL, VT_NULL,
+                VT_UI1, VT_UI2, VT_UI4, VT_UI8, VT_INT, VT_UINT, VT_R4, VT_R8
             }
         },
         Foundation::BSTR
     },
     core::{
-        PCWSTR, Interface
+        PCWSTR, Interface, IUnknown
     }
 };
 
-use crate::utils::WMIError;
-
-
-#[allow(non_camel_case_types)]
-#[non_exhaustive]
-#[derive(Debug)]
-pub enum ValueType {
-    EMPTY,
-    CIM_OBJECT(IWbemClassObjectWrapper),
-    BSTR(String),
-    I1(i8),
-    I2(i16),
-    I4(i32),
-    I8(i64),
-    UI1(u8),
-    UI2(u16),
-    UI4(u32),
-    UI8(u64),
-    R4(f32),
-    R8(f64),
-    BOOL(bool),
-    SAFEARRAY(SAFEARRAY),
-    //CIM_STRING_ARRAY(Vec<String>)
-}
+use crate::{de, event::{EventKind, InstanceEvent}, utils::WMIError, value::{Value, ValueType}};
 
 
 #[derive(Debug)]
@@ -98,6 +79,11 @@ impl IWbemClassObjectWrapper {
     }
 
     pub fn get_property(&self, name: &str) -> Result<Option<(String, ValueType)>, Box<dyn Error>> {
+        Ok(self.read_property(name)?.map(|(value, _)| (name.to_string(), value)))
+    }
+
+    /// The value of `name` along with its CIMTYPE, which tells apart e.g. dates from plain strings
+    pub fn read_property(&self, name: &str) -> Result<Option<(ValueType, i32)>, Box<dyn Error>> {
         let mut variant = VARIANT::default();
         let property = BSTR::from(name);
         let property = property.as_wide();
@@ -112,143 +98,148 @@ impl IWbemClassObjectWrapper {
                 std::ptr::null_mut()
             )?;
 
-            match VARENUM(variant.Anonymous.Anonymous.vt as i32) {
-                VT_UNKNOWN => {
-                    // this unknown type is generally an embedded object
-                    if var_type != CIM_OBJECT.0 {
-                        return Err(Box::new(WMIError::NotCimObject))
-                    }
-
-                    // convert embedded object to IUnknown, then cast to IWbemClassObject
-                    let pVal = variant.Anonymous.Anonymous.Anonymous.punkVal.as_ref().unwrap();
-                    let embeddedObject = pVal.cast::<IWbemClassObject>()?;
-                    ValueType::CIM_OBJECT(Self::new(embeddedObject))
-                }
+            // the VARIANT has no Drop, so whatever it holds has to be freed once it's copied out of,
+            // whether or not that worked
+            let value = Self::read_variant(name, &variant, var_type);
+            VariantClear(&mut variant)?;
 
-                VT_BSTR => {
-                    let bstring = &*variant.Anonymous.Anonymous.Anonymous.bstrVal;
-                    let string = bstring.to_string();
-                    ValueType::BSTR(string)
-                }
-
-                // float 32
-                VT_R4 => {
-                    ValueType::R4(variant.Anonymous.Anonymous.Anonymous.fltVal)
-                }
-
-                // double 64
-                VT_R8 => {
-                    ValueType::R8(variant.Anonymous.Anonymous.Anonymous.dblVal)
-                }
+            value?
+        };
 
-                // 1 byte signed
-                VT_I1 => {
-                    todo!("VT_I1 does not exist?")
-                }
+        Ok(value.map(|v| (v, var_type)))
+    }
 
-                // 2 byte signed
-                VT_I2 => {
-                    ValueType::I2(variant.Anonymous.Anonymous.Anonymous.iVal)
+    /// Copy the value out of a VARIANT from `IWbemClassObject::Get`, leaving it to be cleared
+    unsafe fn read_variant(name: &str, variant: &VARIANT, var_type: i32) -> Result<Option<ValueType>, Box<dyn Error>> {
+        let vt = variant.Anonymous.Anonymous.vt as i32;
+
+        // CIM_FLAG_ARRAY is set on null arrays too, so go by the VARIANT instead
+        if vt & VT_ARRAY.0 != 0 {
+            let array = variant.Anonymous.Anonymous.Anonymous.parray;
+
+            let value = match VARENUM(vt & VT_TYPEMASK.0) {
+                VT_BSTR => ValueType::BSTR_ARRAY(read_array(array, |v: &BSTR| Ok(v.to_string()))?),
+                VT_I1 => ValueType::I1_ARRAY(read_array(array, |v: &i8| Ok(*v))?),
+                VT_I2 => ValueType::I2_ARRAY(read_array(array, |v: &i16| Ok(*v))?),
+                VT_I4 | VT_INT => ValueType::I4_ARRAY(read_array(array, |v: &i32| Ok(*v))?),
+                VT_I8 => ValueType::I8_ARRAY(read_array(array, |v: &i64| Ok(*v))?),
+                VT_UI1 => ValueType::UI1_ARRAY(read_array(array, |v: &u8| Ok(*v))?),
+                VT_UI2 => ValueType::UI2_ARRAY(read_array(array, |v: &u16| Ok(*v))?),
+                VT_UI4 | VT_UINT => ValueType::UI4_ARRAY(read_array(array, |v: &u32| Ok(*v))?),
+                VT_UI8 => ValueType::UI8_ARRAY(read_array(array, |v: &u64| Ok(*v))?),
+                VT_R4 => ValueType::R4_ARRAY(read_array(array, |v: &f32| Ok(*v))?),
+                VT_R8 => ValueType::R8_ARRAY(read_array(array, |v: &f64| Ok(*v))?),
+                // VARIANT_BOOL
+                VT_BOOL => ValueType::BOOL_ARRAY(read_array(array, |v: &i16| Ok(*v != 0))?),
+
+                // embedded objects
+                VT_UNKNOWN if var_type == CIM_OBJECT.0 | CIM_FLAG_ARRAY.0 => {
+                    ValueType::CIM_OBJECT_ARRAY(read_array(array, |v: &Option<IUnknown>| {
+                        let obj = v.as_ref().ok_or(WMIError::NullPointerResult)?;
+                        Ok(Self::new(obj.cast::<IWbemClassObject>()?))
+                    })?)
                 }
 
-                // 4 byte signed
-                VT_I4 | VT_INT => {
-                    ValueType::I4(variant.Anonymous.Anonymous.Anonymous.intVal)
-                }
+                _ => return Err(Box::new(WMIError::UnsupportedType { property: name.to_string(), vartype: vt }))
+            };
 
-                // 8 byte signed
-                VT_I8 => {
-                    ValueType::I8(variant.Anonymous.Anonymous.Anonymous.llVal)
-                }
+            return Ok(Some(with_cim_type(name, value, var_type)?))
+        }
 
-                // 1 byte unsigned
-                VT_UI1 => {
-                    ValueType::UI1(variant.Anonymous.Anonymous.Anonymous.bVal)
+        let value = match VARENUM(vt) {
+            VT_UNKNOWN => {
+                // this unknown type is generally an embedded object
+                if var_type != CIM_OBJECT.0 {
+                    return Err(Box::new(WMIError::NotCimObject))
                 }
 
-                // 2 bytes unsigned
-                VT_UI2 => {
-                    ValueType::UI2(variant.Anonymous.Anonymous.Anonymous.uiVal)
-                }
+                // a null object is the same as a null property
+                let pVal = match variant.Anonymous.Anonymous.Anonymous.punkVal.as_ref() {
+                    Some(v) => v,
+                    None => return Ok(None)
+                };
 
-                // 4 bytes unsigned
-                VT_UI4 | VT_UINT => {
-                    ValueType::UI4(variant.Anonymous.Anonymous.Anonymous.uintVal)
-                }
+                // convert embedded object to IUnknown, then cast to IWbemClassObject
+                let embeddedObject = pVal.cast::<IWbemClassObject>()?;
+                ValueType::CIM_OBJECT(Self::new(embeddedObject))
+            }
 
-                // 8 bytes unsigned
-                VT_UI8 => {
-                    ValueType::UI8(variant.Anonymous.Anonymous.Anonymous.ullVal)
-                }
+            VT_BSTR => {
+                let bstring = &*variant.Anonymous.Anonymous.Anonymous.bstrVal;
+                let string = bstring.to_string();
+                ValueType::BSTR(string)
+            }
 
-                VT_BOOL => {
-                    ValueType::BOOL(variant.Anonymous.Anonymous.Anonymous.boolVal != 0)
-                }
+            // float 32
+            VT_R4 => {
+                ValueType::R4(variant.Anonymous.Anonymous.Anonymous.fltVal)
+            }
 
-                VT_SAFEARRAY => {
-                    let _safearray = variant.Anonymous.Anonymous.Anonymous.parray;
-                    let safearray = *_safearray;
+            // double 64
+            VT_R8 => {
+                ValueType::R8(variant.Anonymous.Anonymous.Anonymous.dblVal)
+            }
 
-                    ValueType::SAFEARRAY(safearray)
-                }
+            // 1 byte signed
+            VT_I1 => {
+                ValueType::I1(variant.Anonymous.Anonymous.Anonymous.cVal.0 as i8)
+            }
 
-                // Nothing
-                VT_EMPTY => {
-                    return Ok(None)
-                }
+            // 2 byte signed
+            VT_I2 => {
+                ValueType::I2(variant.Anonymous.Anonymous.Anonymous.iVal)
+            }
 
-                // NULL
-                VT_NULL => {
-                    return Ok(None)
-                }
+            // 4 byte signed
+            VT_I4 | VT_INT => {
+                ValueType::I4(variant.Anonymous.Anonymous.Anonymous.intVal)
+            }
 
-                VT_VOID => {
-                    todo!("VT_VOID")
-                }
+            // 8 byte signed
+            VT_I8 => {
+                ValueType::I8(variant.Anonymous.Anonymous.Anonymous.llVal)
+            }
 
-                /*CIM_FLAG_ARRAY | CIM_STRING => {
-                    let mut arrs = VecDeque::new();
-                    let _safearray = variant.Anonymous.Anonymous.Anonymous.parray;
-                    let safearray = *_safearray;
+            // 1 byte unsigned
+            VT_UI1 => {
+                ValueType::UI1(variant.Anonymous.Anonymous.Anonymous.bVal)
+            }
 
-                    let mut ptr: *mut c_void = std::mem::zeroed();
+            // 2 bytes unsigned
+            VT_UI2 => {
+                ValueType::UI2(variant.Anonymous.Anonymous.Anonymous.uiVal)
+            }
 
-                    SafeArrayAccessData(
-                        _safearray as *const _,
-                        &mut ptr as *mut _
-                    )?;
+            // 4 bytes unsigned
+            VT_UI4 | VT_UINT => {
+                ValueType::UI4(variant.Anonymous.Anonymous.Anonymous.uintVal)
+            }
 
-                    for i in 0..safearray.cDims as usize {
-                        let slice = std::slice::from_raw_parts(
-                            ptr as *mut BSTR,
-                            safearray.rgsabound[i].cElements as usize
-                        );
-                        arrs.push_back(slice.into_iter().map(|f| f.to_string()).collect::<Vec<String>>());
-                    }
+            // 8 bytes unsigned
+            VT_UI8 => {
+                ValueType::UI8(variant.Anonymous.Anonymous.Anonymous.ullVal)
+            }
 
-                    SafeArrayUnaccessData(
-                        _safearray
-                    )?;
+            VT_BOOL => {
+                ValueType::BOOL(variant.Anonymous.Anonymous.Anonymous.boolVal != 0)
+            }
 
+            // Nothing
+            VT_EMPTY => {
+                return Ok(None)
+            }
 
-                }*/
+            // NULL
+            VT_NULL => {
+                return Ok(None)
+            }
 
-                v => {
-                    //todo!("TODO: Add another ValueType!: {v:?}");
-                    println!("Warning: Skipped {name} with type 0x{:x?}", v.0);
-                    return Ok(None)
-                },
+            v => {
+                return Err(Box::new(WMIError::UnsupportedType { property: name.to_string(), vartype: v.0 }))
             }
         };
 
-        Ok(
-            Some(
-                (
-                    name.to_string(),
-                    value
-                )
-            )
-        )
+        Ok(Some(with_cim_type(name, value, var_type)?))
     }
 
     pub fn get_embedded_object(&self, name: &str) -> Result<IWbemClassObjectWrapper, Box<dyn Error>> {
@@ -266,15 +257,16 @@ impl IWbemClassObjectWrapper {
                 std::ptr::null_mut()
             )?;
 
-            if cim_type != CIM_OBJECT.0 {
-                return Err(Box::new(WMIError::NotCimObject))
-            }
-
             // convert embedded object to IUnknown, then cast to IWbemClassObject
-            let pVal = variant.Anonymous.Anonymous.Anonymous.punkVal.as_ref().unwrap();
-            let processObject = pVal.cast::<IWbemClassObject>()?;
+            let processObject = match variant.Anonymous.Anonymous.Anonymous.punkVal.as_ref() {
+                Some(pVal) if cim_type == CIM_OBJECT.0 => pVal.cast::<IWbemClassObject>().map_err(|e| e.into()),
+                _ => Err(Box::new(WMIError::NotCimObject) as Box<dyn Error>)
+            };
 
-            Self::new(processObject)
+            // the cast took its own reference, so the VARIANT's can go
+            VariantClear(&mut variant)?;
+
+            Self::new(processObject?)
         };
 
         Ok(processObject)
@@ -300,4 +292,165 @@ impl IWbemClassObjectWrapper {
 
         Ok(Some(hashmap))
     }
+
+    /// Copy this object's non-system properties out into plain data
+    pub fn to_value(&self) -> Result<Value, Box<dyn Error>> {
+        let mut object = BTreeMap::new();
+
+        for name in self.get_property_names()?.unwrap_or_default() {
+            if name.starts_with("__") {
+                continue
+            }
+
+            let (value, cim_type) = match self.read_property(&name)? {
+                Some(v) => v,
+                None => {
+                    object.insert(name, Value::Null);
+                    continue
+                }
+            };
+
+            object.insert(name, plain_value(value, cim_type)?);
+        }
+
+        Ok(Value::Object(object))
+    }
+
+    /// Build a `T` out of this object's non-system properties, see [`de::from_properties`]
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<T, WMIError> {
+        let properties = self.get_properties(true)
+            .map_err(|e| WMIError::PropertyReadFailed(e.to_string()))?;
+
+        de::from_properties(properties.unwrap_or_default())
+    }
+
+    /// Split an `__InstanceOperationEvent` into its kind and the instances it carries,
+    /// the `PreviousInstance` too if it was a modification
+    pub fn instance_event(&self) -> Result<InstanceEvent<IWbemClassObjectWrapper>, Box<dyn Error>> {
+        let class = match self.get_property("__CLASS")? {
+            Some((_, ValueType::BSTR(v))) => v,
+            _ => String::new()
+        };
+
+        let target = || self.get_embedded_object("TargetInstance");
+
+        let event = match EventKind::from_class(&class) {
+            Some(EventKind::Created) => InstanceEvent::Created(target()?),
+            Some(EventKind::Modified) => InstanceEvent::Modified {
+                previous: self.get_embedded_object("PreviousInstance")?,
+                target: target()?
+            },
+            Some(EventKind::Deleted) => InstanceEvent::Deleted(target()?),
+            None => return Err(Box::new(WMIError::NotInstanceEvent(class)))
+        };
+
+        Ok(event)
+    }
+
+    /// [`instance_event`](Self::instance_event), with each instance deserialized into a `T`
+    pub fn deserialize_event<T: DeserializeOwned>(&self) -> Result<InstanceEvent<T>, Box<dyn Error>> {
+        Ok(self.instance_event()?.try_map(|v| v.deserialize::<T>())?)
+    }
+}
+
+/// Copy out the elements of a SAFEARRAY
+unsafe fn read_array<T, U>(
+    array: *const SAFEARRAY,
+    convert: impl Fn(&T) -> Result<U, Box<dyn Error>>
+) -> Result<Vec<U>, Box<dyn Error>> {
+    // WMI arrays only ever have the one dimension
+    let lower = SafeArrayGetLBound(array, 1)?;
+    let upper = SafeArrayGetUBound(array, 1)?;
+    let len = (upper - lower + 1).max(0) as usize;
+
+    if len == 0 {
+        return Ok(vec![])
+    }
+
+    let mut ptr: *mut c_void = std::ptr::null_mut();
+    SafeArrayAccessData(array, &mut ptr as *mut _)?;
+
+    let items = std::slice::from_raw_parts(ptr as *const T, len)
+        .iter()
+        .map(convert)
+        .collect();
+
+    SafeArrayUnaccessData(array)?;
+
+    items
+}
+
+/// WMI sends some CIM types as a different VARIANT type, e.g. a uint64 as a string
+/// and a uint32 as a signed int, so put them back the way the class declares them
+fn with_cim_type(name: &str, value: ValueType, cim_type: i32) -> Result<ValueType, WMIError> {
+    let is = |t: CIMTYPE_ENUMERATION| cim_type == t.0;
+    let is_array = |t: CIMTYPE_ENUMERATION| cim_type == t.0 | CIM_FLAG_ARRAY.0;
+    let invalid = |v: &str| WMIError::InvalidNumber { property: name.to_string(), value: v.to_string() };
+
+    let value = match value {
+        ValueType::BSTR(v) if is(CIM_UINT64) => ValueType::UI8(v.parse().map_err(|_| invalid(&v))?),
+        ValueType::BSTR(v) if is(CIM_SINT64) => ValueType::I8(v.parse().map_err(|_| invalid(&v))?),
+        ValueType::I4(v) if is(CIM_UINT32) => ValueType::UI4(v as u32),
+        ValueType::I4(v) if is(CIM_UINT16) => ValueType::UI2(v as u16),
+        ValueType::I2(v) if is(CIM_SINT8) => ValueType::I1(v as i8),
+
+        ValueType::BSTR_ARRAY(v) if is_array(CIM_UINT64) => ValueType::UI8_ARRAY(
+            v.iter().map(|s| s.parse().map_err(|_| invalid(s))).collect::<Result<_, _>>()?
+        ),
+        ValueType::BSTR_ARRAY(v) if is_array(CIM_SINT64) => ValueType::I8_ARRAY(
+            v.iter().map(|s| s.parse().map_err(|_| invalid(s))).collect::<Result<_, _>>()?
+        ),
+        ValueType::I4_ARRAY(v) if is_array(CIM_UINT32) => ValueType::UI4_ARRAY(v.into_iter().map(|v| v as u32).collect()),
+        ValueType::I4_ARRAY(v) if is_array(CIM_UINT16) => ValueType::UI2_ARRAY(v.into_iter().map(|v| v as u16).collect()),
+        ValueType::I2_ARRAY(v) if is_array(CIM_SINT8) => ValueType::I1_ARRAY(v.into_iter().map(|v| v as i8).collect()),
+
+        v => v
+    };
+
+    Ok(value)
+}
+
+/// `value` as plain data, using its CIMTYPE to tell dates and references apart from strings
+fn plain_value(value: ValueType, cim_type: i32) -> Result<Value, Box<dyn Error>> {
+    fn array<T>(items: Vec<T>, wrap: fn(T) -> ValueType, cim_type: i32) -> Result<Value, Box<dyn Error>> {
+        let items = items.into_iter()
+            .map(|v| plain_value(wrap(v), cim_type & !CIM_FLAG_ARRAY.0))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Value::Array(items))
+    }
+
+    let value = match value {
+        ValueType::EMPTY => Value::Null,
+        ValueType::CIM_OBJECT(obj) => obj.to_value()?,
+        ValueType::BSTR(v) if cim_type == CIM_DATETIME.0 => Value::Datetime(v),
+        ValueType::BSTR(v) if cim_type == CIM_REFERENCE.0 => Value::Reference(v),
+        ValueType::BSTR(v) => Value::String(v),
+        ValueType::I1(v) => Value::Int(v.into()),
+        ValueType::I2(v) => Value::Int(v.into()),
+        ValueType::I4(v) => Value::Int(v.into()),
+        ValueType::I8(v) => Value::Int(v),
+        ValueType::UI1(v) => Value::UInt(v.into()),
+        ValueType::UI2(v) => Value::UInt(v.into()),
+        ValueType::UI4(v) => Value::UInt(v.into()),
+        ValueType::UI8(v) => Value::UInt(v),
+        ValueType::R4(v) => Value::Float(v.into()),
+        ValueType::R8(v) => Value::Float(v),
+        ValueType::BOOL(v) => Value::Bool(v),
+        ValueType::CIM_OBJECT_ARRAY(v) => array(v, ValueType::CIM_OBJECT, cim_type)?,
+        ValueType::BSTR_ARRAY(v) => array(v, ValueType::BSTR, cim_type)?,
+        ValueType::I1_ARRAY(v) => array(v, ValueType::I1, cim_type)?,
+        ValueType::I2_ARRAY(v) => array(v, ValueType::I2, cim_type)?,
+        ValueType::I4_ARRAY(v) => array(v, ValueType::I4, cim_type)?,
+        ValueType::I8_ARRAY(v) => array(v, ValueType::I8, cim_type)?,
+        ValueType::UI1_ARRAY(v) => array(v, ValueType::UI1, cim_type)?,
+        ValueType::UI2_ARRAY(v) => array(v, ValueType::UI2, cim_type)?,
+        ValueType::UI4_ARRAY(v) => array(v, ValueType::UI4, cim_type)?,
+        ValueType::UI8_ARRAY(v) => array(v, ValueType::UI8, cim_type)?,
+        ValueType::R4_ARRAY(v) => array(v, ValueType::R4, cim_type)?,
+        ValueType::R8_ARRAY(v) => array(v, ValueType::R8, cim_type)?,
+        ValueType::BOOL_ARRAY(v) => array(v, ValueType::BOOL, cim_type)?
+    };
+
+    Ok(value)
 }
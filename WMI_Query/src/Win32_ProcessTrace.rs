@@ -0,0 +1,45 @@
+use crate::de::WMIClass;
+
+use serde::Deserialize;
+
+/**
+ * Sent as soon as a process starts, instead of being polled for like `__InstanceCreationEvent`.
+ * Subscribing to it needs admin.
+ */
+#[allow(non_camel_case_types)]
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct Win32_ProcessStartTrace {
+    pub ProcessName: String,
+    pub ProcessID: u32,
+    pub ParentProcessID: u32,
+    pub SessionID: u32,
+    pub Sid: Vec<u8>,
+    /// 100ns ticks since 1601-01-01 UTC
+    pub TIME_CREATED: u64
+}
+
+impl WMIClass for Win32_ProcessStartTrace {
+    const CLASS: &'static str = "Win32_ProcessStartTrace";
+}
+
+/**
+ * Sent as soon as a process exits. Subscribing to it needs admin.
+ */
+#[allow(non_camel_case_types)]
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct Win32_ProcessStopTrace {
+    pub ProcessName: String,
+    pub ProcessID: u32,
+    pub ParentProcessID: u32,
+    pub SessionID: u32,
+    pub Sid: Vec<u8>,
+    pub ExitStatus: u32,
+    /// 100ns ticks since 1601-01-01 UTC
+    pub TIME_CREATED: u64
+}
+
+impl WMIClass for Win32_ProcessStopTrace {
+    const CLASS: &'static str = "Win32_ProcessStopTrace";
+}
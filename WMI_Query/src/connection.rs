@@ -0,0 +1,245 @@
+use crate::{
+    de::WMIClass, event::{self, EventKind}, event_sink::EventSink, query::QueryResults, utils::WMIError, wql::{self, Query},
+    ObjectWrapper::IWbemClassObjectWrapper
+};
+
+use log::debug;
+use std::{error::Error, ops::Deref, time::Duration};
+use async_channel::{unbounded, Receiver};
+
+use windows::{
+    Win32::{
+        System::{
+            Wmi::{
+                WbemLocator, IWbemLocator, IUnsecuredApartment, IWbemServices, UnsecuredApartment,
+                IWbemObjectSink,
+                WBEM_FLAG_SEND_STATUS, WBEM_E_UNPARSABLE_QUERY,
+                WBEM_FLAG_FORWARD_ONLY, WBEM_FLAG_RETURN_IMMEDIATELY
+            },
+            Com::{
+                CoInitializeEx, COINIT_MULTITHREADED, RPC_C_AUTHN_LEVEL_DEFAULT, RPC_C_IMP_LEVEL_IMPERSONATE, EOAC_NONE,
+                CoCreateInstance, CLSCTX_INPROC_SERVER,
+                CoSetProxyBlanket, RPC_C_AUTHN_LEVEL_CALL, CLSCTX_LOCAL_SERVER
+            },
+            Rpc::{
+                RPC_C_AUTHN_WINNT, RPC_C_AUTHN_NONE
+            }
+        },
+        Foundation::{
+            BSTR, RPC_E_TOO_LATE
+        }
+    }, core::{Interface, IUnknown, IntoParam}
+};
+
+
+pub struct WMIConnection {
+    // service used for actual calls
+    pub pSvc: IWbemServices,
+    pUnsecApp: IUnsecuredApartment
+}
+
+impl WMIConnection {
+    pub fn new() -> Result<Self, windows::core::Error> {
+        unsafe {
+            CoInitializeEx(std::ptr::null(), COINIT_MULTITHREADED)?;
+
+            //
+            // https://github.com/microsoft/win32metadata/issues/837
+            // https://github.com/microsoft/windows-rs/issues/1610
+            //
+            #[link(name = "windows")]
+            extern "system" {
+                fn CoInitializeSecurity(
+                    psecdesc: *const windows::Win32::Security::SECURITY_DESCRIPTOR,
+                    cauthsvc: i32,
+                    asauthsvc: *const windows::Win32::System::Com::SOLE_AUTHENTICATION_SERVICE,
+                    preserved1: *const ::core::ffi::c_void,
+                    dwauthnlevel: windows::Win32::System::Com::RPC_C_AUTHN_LEVEL,
+                    dwimplevel: windows::Win32::System::Com::RPC_C_IMP_LEVEL,
+                    pauthlist: *const ::core::ffi::c_void,
+                    dwcapabilities: windows::Win32::System::Com::EOLE_AUTHENTICATION_CAPABILITIES,
+                    preserved3: *const ::core::ffi::c_void
+                ) -> ::windows::core::HRESULT;
+            }
+
+            //
+            // https://github.com/microsoft/win32metadata/issues/837
+            // https://github.com/microsoft/windows-rs/issues/1610
+            //
+            let res = CoInitializeSecurity(
+                std::ptr::null(),
+                -1,
+                std::ptr::null(),
+                std::ptr::null(),
+                RPC_C_AUTHN_LEVEL_DEFAULT,
+                RPC_C_IMP_LEVEL_IMPERSONATE,
+                std::ptr::null(),
+                EOAC_NONE,
+                std::ptr::null()
+            );
+
+            // it can only be done once per process, so a second connection finds it already done
+            if res != RPC_E_TOO_LATE {
+                res.ok()?;
+            }
+
+            // can't put in -1 due to bug on 0.34.0
+            //
+            // https://github.com/microsoft/win32metadata/issues/837
+            // https://github.com/microsoft/windows-rs/issues/1610
+            //
+            /*
+            CoInitializeSecurity(
+                std::ptr::null(),
+                // should be -1
+                &[],
+                std::ptr::null(),
+                RPC_C_AUTHN_LEVEL_DEFAULT,
+                RPC_C_IMP_LEVEL_IMPERSONATE,
+                std::ptr::null(),
+                EOAC_NONE,
+                std::ptr::null()
+            )?;
+            */
+
+            let pLoc: IWbemLocator = CoCreateInstance(&WbemLocator, None, CLSCTX_INPROC_SERVER)?;
+
+            let pSvc = pLoc.ConnectServer(
+                BSTR::from("ROOT\\CIMV2"),
+                None,
+                None,
+                None,
+                0,
+                None,
+                None
+            )?;
+
+            CoSetProxyBlanket(
+                &pSvc,
+                RPC_C_AUTHN_WINNT,
+                RPC_C_AUTHN_NONE,
+                None,
+                RPC_C_AUTHN_LEVEL_CALL,
+                RPC_C_IMP_LEVEL_IMPERSONATE,
+                std::ptr::null(),
+                EOAC_NONE
+            )?;
+
+            let pUnsecApp: IUnsecuredApartment = CoCreateInstance(&UnsecuredApartment, None, CLSCTX_LOCAL_SERVER)?;
+
+            Ok(Self {
+                pSvc,
+                pUnsecApp
+            })
+        }
+    }
+
+    /// Run a WQL `SELECT` and go through the rows as they come in
+    pub fn exec_query(&self, query: &str) -> Result<QueryResults, Box<dyn Error>> {
+        let enumerator = unsafe {
+            self.pSvc.ExecQuery(
+                BSTR::from("WQL"),
+                BSTR::from(query),
+                WBEM_FLAG_FORWARD_ONLY.0 | WBEM_FLAG_RETURN_IMMEDIATELY.0,
+                None
+            )
+        };
+
+        match enumerator {
+            Ok(v) => Ok(QueryResults::new(v)),
+            Err(e) if e.code().0 == WBEM_E_UNPARSABLE_QUERY.0 => Err(Box::new(WMIError::WbemUnparsableQuery)),
+            Err(e) => Err(Box::new(e))
+        }
+    }
+
+    /// Every instance of `T`'s class
+    pub fn query<T: WMIClass>(&self) -> Result<Vec<T>, Box<dyn Error>> {
+        let mut rows = vec![];
+
+        for row in self.exec_query(&Query::select_all().from_class::<T>().to_string())? {
+            rows.push(row?.deserialize::<T>()?);
+        }
+
+        Ok(rows)
+    }
+
+    /// Get told about `kinds` of changes to instances of `T`'s class, checked for every `within`.
+    /// Read each event with [`IWbemClassObjectWrapper::deserialize_event`].
+    pub fn subscribe<T: WMIClass>(&self, kinds: &[EventKind], within: Duration) -> Result<AsyncQueryReceiver<'_>, Box<dyn Error>> {
+        self.exec_notification_query_async(&event::event_query(T::CLASS, kinds, within).to_string())
+    }
+
+    /// Get every extrinsic event of `T`'s class, like `Win32_ProcessStartTrace`.
+    /// These are sent as they happen, so they don't need a `WITHIN`.
+    pub fn subscribe_extrinsic<T: WMIClass>(&self) -> Result<AsyncQueryReceiver<'_>, Box<dyn Error>> {
+        self.exec_notification_query_async(&Query::select_all().from_class::<T>().to_string())
+    }
+
+    /// The query is checked here first, so a bad one says what's wrong with it instead of just being unparsable
+    pub fn exec_notification_query_async(&self, query: &str) -> Result<AsyncQueryReceiver, Box<dyn Error>> {
+        wql::parse(query).map_err(WMIError::InvalidQuery)?;
+
+        let (tx, rx) = unbounded();
+
+        let event_sink = EventSink::new(tx);
+        let unknown: IUnknown = event_sink.into();
+
+        let sink: IWbemObjectSink;
+        unsafe {
+            let pStubUnk: IUnknown = self.pUnsecApp.CreateObjectStub(unknown)?;
+
+            sink = pStubUnk.cast()?;
+
+            //let pctx: IWbemContext = CoCreateInstance(&WbemContext, None, CLSCTX_LOCAL_SERVER)?;
+
+            let res = (Interface::vtable(&self.pSvc).ExecNotificationQueryAsync)(
+                core::mem::transmute_copy(&self.pSvc),
+                BSTR::from("WQL").into_param().abi(),
+                BSTR::from(query).into_param().abi(),
+                WBEM_FLAG_SEND_STATUS.0,
+                std::ptr::null_mut(),
+                core::mem::transmute_copy(&sink)
+            ).ok();
+
+            if let Err(e) = res {
+                if e.code().0 == WBEM_E_UNPARSABLE_QUERY.0 {
+                    return Err(Box::new(WMIError::WbemUnparsableQuery))
+                }
+
+                // e.g. an unknown class, or one only admins can subscribe to
+                return Err(Box::new(e))
+            }
+        }
+
+        let asyncreceiver = AsyncQueryReceiver {
+            receiver: rx,
+            pSvc: &self.pSvc,
+            pStubSink: sink
+        };
+
+        Ok(asyncreceiver)
+    }
+}
+
+pub struct AsyncQueryReceiver<'a> {
+    pub receiver: Receiver<Result<IWbemClassObjectWrapper, WMIError>>,
+    pub pSvc: &'a IWbemServices,
+    pub pStubSink: IWbemObjectSink
+}
+
+impl Deref for AsyncQueryReceiver<'_> {
+    type Target = Receiver<Result<IWbemClassObjectWrapper, WMIError>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.receiver
+    }
+}
+
+impl<'a> Drop for AsyncQueryReceiver<'_> {
+    fn drop(&mut self) {
+        debug!("Canceling async call");
+        unsafe {
+            let _ = self.pSvc.CancelAsyncCall(&self.pStubSink);
+        }
+    }
+}
@@ -0,0 +1,570 @@
+use std::{fmt, str::FromStr, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+
+const MICROS_PER_SEC: i64 = 1_000_000;
+const SECS_PER_DAY: i64 = 86_400;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum DateTimeError {
+    #[error("Invalid CIM datetime `{value}` -> {reason}")]
+    InvalidDateTime {
+        value: String,
+        reason: &'static str
+    },
+
+    #[error("Invalid CIM interval `{value}` -> {reason}")]
+    InvalidInterval {
+        value: String,
+        reason: &'static str
+    },
+
+    #[error("{micros} microseconds since the Unix epoch at {offset} minutes from UTC can't be a CIM datetime -> {reason}")]
+    OutOfRange {
+        micros: i64,
+        offset: i16,
+        reason: &'static str
+    }
+}
+
+/// A CIM_DATETIME timestamp, written as `yyyymmddHHMMSS.mmmmmmsUUU`,
+/// where `sUUU` is how many minutes ahead (`+`) or behind (`-`) of UTC it was written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CimDateTime {
+    /// Microseconds since the Unix epoch, in UTC
+    micros: i64,
+    /// Minutes ahead of UTC, only used to write it back out the same way
+    offset: i16
+}
+
+impl CimDateTime {
+    /// Only offsets of up to 999 minutes and years 0 to 9999 in local time can be written out
+    pub fn from_unix_micros(micros: i64, offset: i16) -> Result<Self, DateTimeError> {
+        let out_of_range = |reason| DateTimeError::OutOfRange { micros, offset, reason };
+
+        if offset.unsigned_abs() > 999 {
+            return Err(out_of_range("the offset has to be within 999 minutes"));
+        }
+
+        let first = days_from_civil(0, 1, 1) * SECS_PER_DAY * MICROS_PER_SEC;
+        let last = days_from_civil(10_000, 1, 1) * SECS_PER_DAY * MICROS_PER_SEC - 1;
+
+        let local = micros.checked_add(offset as i64 * 60 * MICROS_PER_SEC);
+        if !local.is_some_and(|v| (first..=last).contains(&v)) {
+            return Err(out_of_range("the year has to be from 0 to 9999"));
+        }
+
+        Ok(Self {
+            micros,
+            offset
+        })
+    }
+
+    /// Microseconds since the Unix epoch, in UTC
+    pub fn unix_micros(&self) -> i64 {
+        self.micros
+    }
+
+    /// Minutes ahead of UTC it was written in
+    pub fn offset(&self) -> i16 {
+        self.offset
+    }
+
+    pub fn to_system_time(&self) -> SystemTime {
+        let since_epoch = Duration::from_micros(self.micros.unsigned_abs());
+
+        if self.micros >= 0 {
+            UNIX_EPOCH + since_epoch
+        } else {
+            UNIX_EPOCH - since_epoch
+        }
+    }
+}
+
+impl FromStr for CimDateTime {
+    type Err = DateTimeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason| DateTimeError::InvalidDateTime { value: s.to_string(), reason };
+
+        if s.len() != 25 || !s.is_ascii() {
+            return Err(invalid("expected 25 characters"));
+        }
+
+        if s.contains('*') {
+            return Err(invalid("fields left unspecified with `*` aren't supported"));
+        }
+
+        if &s[14..15] != "." {
+            return Err(invalid("expected `.` after the seconds"));
+        }
+
+        let field = |range: std::ops::Range<usize>| digits(&s[range]).ok_or_else(|| invalid("expected digits"));
+
+        let year = field(0..4)?;
+        let month = field(4..6)?;
+        let day = field(6..8)?;
+        let hour = field(8..10)?;
+        let minute = field(10..12)?;
+        let second = field(12..14)?;
+        let micros = field(15..21)?;
+        let offset = field(22..25)? as i64;
+
+        let offset = match &s[21..22] {
+            "+" => offset,
+            "-" => -offset,
+            _ => return Err(invalid("expected `+` or `-` before the UTC offset"))
+        };
+
+        if !(1..=12).contains(&month) {
+            return Err(invalid("month out of range"));
+        }
+
+        if day < 1 || day > days_in_month(year, month) {
+            return Err(invalid("day out of range"));
+        }
+
+        if hour > 23 || minute > 59 || second > 59 {
+            return Err(invalid("time of day out of range"));
+        }
+
+        let local = days_from_civil(year as i64, month, day) * SECS_PER_DAY
+            + hour as i64 * 3600
+            + minute as i64 * 60
+            + second as i64;
+
+        // local time is ahead of UTC by the offset
+        let utc = local - offset * 60;
+
+        Ok(Self {
+            micros: utc * MICROS_PER_SEC + micros as i64,
+            offset: offset as i16
+        })
+    }
+}
+
+impl fmt::Display for CimDateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let local = self.micros + self.offset as i64 * 60 * MICROS_PER_SEC;
+        let secs = local.div_euclid(MICROS_PER_SEC);
+        let micros = local.rem_euclid(MICROS_PER_SEC);
+
+        let (year, month, day) = civil_from_days(secs.div_euclid(SECS_PER_DAY));
+        let time = secs.rem_euclid(SECS_PER_DAY);
+        let sign = if self.offset < 0 { '-' } else { '+' };
+
+        write!(
+            f,
+            "{year:04}{month:02}{day:02}{:02}{:02}{:02}.{micros:06}{sign}{:03}",
+            time / 3600,
+            time % 3600 / 60,
+            time % 60,
+            self.offset.unsigned_abs()
+        )
+    }
+}
+
+/// A CIM_DATETIME interval, written as `ddddddddHHMMSS.mmmmmm:000`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct CimInterval(pub Duration);
+
+impl FromStr for CimInterval {
+    type Err = DateTimeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason| DateTimeError::InvalidInterval { value: s.to_string(), reason };
+
+        if s.len() != 25 || !s.is_ascii() {
+            return Err(invalid("expected 25 characters"));
+        }
+
+        if &s[14..15] != "." || &s[21..] != ":000" {
+            return Err(invalid("expected `.` after the seconds and `:000` at the end"));
+        }
+
+        let field = |range: std::ops::Range<usize>| digits(&s[range]).ok_or_else(|| invalid("expected digits"));
+
+        let days = field(0..8)? as u64;
+        let hours = field(8..10)? as u64;
+        let minutes = field(10..12)? as u64;
+        let seconds = field(12..14)? as u64;
+        let micros = field(15..21)?;
+
+        if hours > 23 || minutes > 59 || seconds > 59 {
+            return Err(invalid("time of day out of range"));
+        }
+
+        let secs = days * SECS_PER_DAY as u64 + hours * 3600 + minutes * 60 + seconds;
+
+        Ok(Self(Duration::new(secs, micros * 1000)))
+    }
+}
+
+/// The longest interval that fits in the 8 digits of days
+const MAX_INTERVAL_SECS: u64 = 99_999_999 * SECS_PER_DAY as u64 + SECS_PER_DAY as u64 - 1;
+
+impl fmt::Display for CimInterval {
+    // anything under a microsecond is dropped, and anything too long for 8 digits of days is written as the longest there is
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.0.as_secs();
+        let (secs, micros) = match secs > MAX_INTERVAL_SECS {
+            true => (MAX_INTERVAL_SECS, 999_999),
+            false => (secs, self.0.subsec_micros())
+        };
+
+        write!(
+            f,
+            "{:08}{:02}{:02}{:02}.{:06}:000",
+            secs / SECS_PER_DAY as u64,
+            secs % SECS_PER_DAY as u64 / 3600,
+            secs % 3600 / 60,
+            secs % 60,
+            micros
+        )
+    }
+}
+
+/// Like `Win32_Process::UserModeTime`, a count of 100ns ticks, which WMI sends as a string
+pub fn deserialize_ticks<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    struct Ticks;
+
+    impl<'de> de::Visitor<'de> for Ticks {
+        type Value = Duration;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a count of 100ns ticks")
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+            Ok(Duration::new(v / 10_000_000, (v % 10_000_000) as u32 * 100))
+        }
+
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+            u64::try_from(v)
+                .map_err(|_| E::invalid_value(de::Unexpected::Signed(v), &self))
+                .and_then(|v| self.visit_u64(v))
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            v.parse::<u64>()
+                .map_err(|_| E::invalid_value(de::Unexpected::Str(v), &self))
+                .and_then(|v| self.visit_u64(v))
+        }
+    }
+
+    deserializer.deserialize_any(Ticks)
+}
+
+impl<'de> Deserialize<'de> for CimDateTime {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for CimDateTime {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for CimInterval {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for CimInterval {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Only plain ASCII digits, no signs or spaces like `str::parse` would take
+fn digits(s: &str) -> Option<u32> {
+    if !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    s.parse().ok()
+}
+
+fn is_leap_year(year: u32) -> bool {
+    year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400))
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31
+    }
+}
+
+// https://howardhinnant.github.io/date_algorithms.html
+/// Days since 1970-01-01 in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The inverse of `days_from_civil`
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde::de::value::{self, I64Deserializer, StrDeserializer, U64Deserializer};
+
+    fn parse(s: &str) -> Result<CimDateTime, DateTimeError> {
+        s.parse()
+    }
+
+    fn reason(result: Result<CimDateTime, DateTimeError>) -> &'static str {
+        match result {
+            Err(DateTimeError::InvalidDateTime { reason, .. }) => reason,
+            v => panic!("expected an invalid datetime, got {v:?}")
+        }
+    }
+
+    #[test]
+    fn epoch() {
+        let v = parse("19700101000000.000000+000").unwrap();
+        assert_eq!(v.unix_micros(), 0);
+        assert_eq!(v.offset(), 0);
+        assert_eq!(v.to_system_time(), UNIX_EPOCH);
+    }
+
+    #[test]
+    fn leap_days() {
+        // 2000 is divisible by 400, 2024 by 4
+        assert_eq!(parse("20000229120000.000000+000").unwrap().unix_micros(), 951_825_600 * MICROS_PER_SEC);
+        assert_eq!(parse("20240229000000.000000+000").unwrap().unix_micros(), 1_709_164_800 * MICROS_PER_SEC);
+        assert_eq!(parse("20240301000000.000000+000").unwrap().unix_micros(), 1_709_251_200 * MICROS_PER_SEC);
+    }
+
+    #[test]
+    fn feb_29_on_non_leap_years() {
+        // 1900 is divisible by 100 but not 400
+        assert_eq!(reason(parse("19000229000000.000000+000")), "day out of range");
+        assert_eq!(reason(parse("20230229000000.000000+000")), "day out of range");
+        assert_eq!(reason(parse("20240230000000.000000+000")), "day out of range");
+    }
+
+    #[test]
+    fn out_of_range_fields() {
+        assert_eq!(reason(parse("20241301000000.000000+000")), "month out of range");
+        assert_eq!(reason(parse("20240001000000.000000+000")), "month out of range");
+        assert_eq!(reason(parse("20240431000000.000000+000")), "day out of range");
+        assert_eq!(reason(parse("20240100000000.000000+000")), "day out of range");
+        assert_eq!(reason(parse("20240101240000.000000+000")), "time of day out of range");
+        assert_eq!(reason(parse("20240101006000.000000+000")), "time of day out of range");
+        assert_eq!(reason(parse("20240101000060.000000+000")), "time of day out of range");
+    }
+
+    #[test]
+    fn positive_offset() {
+        // 02:00 two hours ahead of UTC is midnight UTC
+        let v = parse("19700101020000.000000+120").unwrap();
+        assert_eq!(v.unix_micros(), 0);
+        assert_eq!(v.offset(), 120);
+        assert_eq!(v.to_string(), "19700101020000.000000+120");
+    }
+
+    #[test]
+    fn negative_offset() {
+        // 19:00 five hours behind UTC is midnight UTC the next day
+        let v = parse("19691231190000.000000-300").unwrap();
+        assert_eq!(v.unix_micros(), 0);
+        assert_eq!(v.offset(), -300);
+        assert_eq!(v.to_string(), "19691231190000.000000-300");
+    }
+
+    #[test]
+    fn offsets_are_the_same_instant() {
+        let utc = parse("20240615120000.000000+000").unwrap();
+        let ahead = parse("20240615173000.000000+330").unwrap();
+        let behind = parse("20240615040000.000000-480").unwrap();
+
+        assert_eq!(utc.unix_micros(), ahead.unix_micros());
+        assert_eq!(utc.unix_micros(), behind.unix_micros());
+        // they're still written back out the way they came
+        assert_ne!(utc, ahead);
+    }
+
+    #[test]
+    fn before_1970() {
+        let v = parse("19691231235959.999999+000").unwrap();
+        assert_eq!(v.unix_micros(), -1);
+        assert_eq!(v.to_system_time(), UNIX_EPOCH - Duration::from_micros(1));
+
+        let v = parse("16010101000000.000000+000").unwrap();
+        assert_eq!(v.unix_micros(), -11_644_473_600 * MICROS_PER_SEC);
+        assert_eq!(v.to_string(), "16010101000000.000000+000");
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for s in [
+            "19700101000000.000000+000",
+            "19691231235959.999999+000",
+            "20000229235959.123456-720",
+            "20381231235959.999999+840",
+            "16010101000000.000001+000",
+            "99991231235959.999999+000",
+            "00010101000000.000000+000"
+        ] {
+            let v = parse(s).unwrap();
+            assert_eq!(v.to_string(), s);
+            assert_eq!(parse(&v.to_string()).unwrap(), v);
+        }
+    }
+
+    #[test]
+    fn from_unix_micros_round_trips() {
+        for micros in [0, 1, -1, 1_709_164_800_123_456, -11_644_473_600_000_000] {
+            for offset in [0, 60, -60, 345, -570] {
+                let v = CimDateTime::from_unix_micros(micros, offset).unwrap();
+                assert_eq!(parse(&v.to_string()).unwrap(), v);
+            }
+        }
+    }
+
+    #[test]
+    fn from_unix_micros_bounds() {
+        let out_of_range = |micros, offset| match CimDateTime::from_unix_micros(micros, offset) {
+            Err(DateTimeError::OutOfRange { reason, .. }) => reason,
+            v => panic!("expected it to be out of range, got {v:?}")
+        };
+
+        let first = parse("00000101000000.000000+000").unwrap().unix_micros();
+        let last = parse("99991231235959.999999+000").unwrap().unix_micros();
+
+        for (micros, offset) in [(0, 999), (0, -999), (first, 0), (last, 0), (first + 60 * MICROS_PER_SEC, -1), (last - 60 * MICROS_PER_SEC, 1)] {
+            let v = CimDateTime::from_unix_micros(micros, offset).unwrap();
+            assert_eq!(parse(&v.to_string()).unwrap(), v);
+        }
+
+        assert_eq!(out_of_range(0, 1000), "the offset has to be within 999 minutes");
+        assert_eq!(out_of_range(0, i16::MIN), "the offset has to be within 999 minutes");
+        assert_eq!(out_of_range(first - 1, 0), "the year has to be from 0 to 9999");
+        assert_eq!(out_of_range(last + 1, 0), "the year has to be from 0 to 9999");
+        // still 9999 in UTC, but not where it was written
+        assert_eq!(out_of_range(last, 1), "the year has to be from 0 to 9999");
+        assert_eq!(out_of_range(first, -1), "the year has to be from 0 to 9999");
+        assert_eq!(out_of_range(i64::MAX, 999), "the year has to be from 0 to 9999");
+        assert_eq!(out_of_range(i64::MIN, -999), "the year has to be from 0 to 9999");
+    }
+
+    #[test]
+    fn wrong_length() {
+        assert_eq!(reason(parse("")), "expected 25 characters");
+        assert_eq!(reason(parse("20240101000000.000000+00")), "expected 25 characters");
+        assert_eq!(reason(parse("20240101000000.000000+0000")), "expected 25 characters");
+        // 25 bytes, but not 25 characters
+        assert_eq!(reason(parse("2024010100000é.000000+00")), "expected 25 characters");
+    }
+
+    #[test]
+    fn unspecified_fields() {
+        assert_eq!(reason(parse("2024****000000.000000+000")), "fields left unspecified with `*` aren't supported");
+        assert_eq!(reason(parse("20240101000000.******+***")), "fields left unspecified with `*` aren't supported");
+    }
+
+    #[test]
+    fn bad_separators() {
+        assert_eq!(reason(parse("20240101000000,000000+000")), "expected `.` after the seconds");
+        assert_eq!(reason(parse("20240101000000.000000:000")), "expected `+` or `-` before the UTC offset");
+        assert_eq!(reason(parse("2024-101000000.000000+000")), "expected digits");
+        assert_eq!(reason(parse("20240101000000.00 000+000")), "expected digits");
+        assert_eq!(reason(parse("20240101000000.000000++00")), "expected digits");
+    }
+
+    #[test]
+    fn serde_goes_through_strings() {
+        let s = "20240229120000.500000-060";
+
+        let v = CimDateTime::deserialize(StrDeserializer::<value::Error>::new(s)).unwrap();
+        assert_eq!(v, parse(s).unwrap());
+
+        let bad = CimDateTime::deserialize(StrDeserializer::<value::Error>::new("20240229"));
+        assert!(bad.unwrap_err().to_string().contains("expected 25 characters"));
+    }
+
+    fn interval(s: &str) -> Result<CimInterval, DateTimeError> {
+        s.parse()
+    }
+
+    #[test]
+    fn intervals() {
+        assert_eq!(interval("00000000000000.000000:000").unwrap(), CimInterval::default());
+        assert_eq!(interval("00000001020304.000005:000").unwrap().0, Duration::new(86_400 + 2 * 3600 + 3 * 60 + 4, 5_000));
+        assert_eq!(interval("99999999235959.999999:000").unwrap().0, Duration::new(MAX_INTERVAL_SECS, 999_999_000));
+    }
+
+    #[test]
+    fn interval_round_trips() {
+        for s in ["00000000000000.000000:000", "00000001020304.000005:000", "12345678235959.999999:000", "99999999235959.999999:000"] {
+            assert_eq!(interval(s).unwrap().to_string(), s);
+        }
+    }
+
+    #[test]
+    fn interval_drops_nanoseconds() {
+        assert_eq!(CimInterval(Duration::new(1, 1_999)).to_string(), "00000000000001.000001:000");
+    }
+
+    #[test]
+    fn interval_overflowing_the_days() {
+        let longest = "99999999235959.999999:000";
+
+        // one more second would need a 9th digit of days
+        assert_eq!(CimInterval(Duration::from_secs(MAX_INTERVAL_SECS + 1)).to_string(), longest);
+        assert_eq!(CimInterval(Duration::MAX).to_string(), longest);
+        assert_eq!(interval(&CimInterval(Duration::MAX).to_string()).unwrap().0, Duration::new(MAX_INTERVAL_SECS, 999_999_000));
+    }
+
+    #[test]
+    fn invalid_intervals() {
+        let reason = |s: &str| match interval(s) {
+            Err(DateTimeError::InvalidInterval { reason, .. }) => reason,
+            v => panic!("expected an invalid interval, got {v:?}")
+        };
+
+        assert_eq!(reason("0000000000000.000000:000"), "expected 25 characters");
+        assert_eq!(reason("00000000000000.000000+000"), "expected `.` after the seconds and `:000` at the end");
+        assert_eq!(reason("00000000000000:000000:000"), "expected `.` after the seconds and `:000` at the end");
+        assert_eq!(reason("0000000*000000.000000:000"), "expected digits");
+        assert_eq!(reason("00000000240000.000000:000"), "time of day out of range");
+        assert_eq!(reason("00000000006000.000000:000"), "time of day out of range");
+    }
+
+    #[test]
+    fn ticks() {
+        assert_eq!(deserialize_ticks(U64Deserializer::<value::Error>::new(10_000_000)).unwrap(), Duration::from_secs(1));
+        assert_eq!(deserialize_ticks(StrDeserializer::<value::Error>::new("15")).unwrap(), Duration::from_nanos(1_500));
+        assert!(deserialize_ticks(I64Deserializer::<value::Error>::new(-1)).is_err());
+        assert!(deserialize_ticks(StrDeserializer::<value::Error>::new("-1")).is_err());
+    }
+}
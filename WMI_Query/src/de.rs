@@ -0,0 +1,311 @@
+use crate::{utils::WMIError, value::{Value, ValueType}};
+
+use std::collections::HashMap;
+
+use serde::de::{value::{MapDeserializer, SeqDeserializer}, DeserializeOwned, Deserializer, IntoDeserializer, Visitor};
+
+
+/// A WMI class whose instances can be deserialized from the rows of a query
+pub trait WMIClass: DeserializeOwned {
+    /// The name to select from, e.g. `Win32_Process`
+    const CLASS: &'static str;
+}
+
+/// Build a `T` out of the properties of a WMI object.
+/// Null properties are left out by WMI, so they only work for `Option` or `#[serde(default)]` fields.
+pub fn from_properties<T: DeserializeOwned>(properties: HashMap<String, ValueType>) -> Result<T, WMIError> {
+    T::deserialize(MapDeserializer::new(properties.into_iter()))
+}
+
+/// Build a `T` out of a [`Value`], e.g. one made with `IWbemClassObjectWrapper::to_value`
+pub fn from_value<T: DeserializeOwned>(value: Value) -> Result<T, WMIError> {
+    T::deserialize(value)
+}
+
+impl<'de> IntoDeserializer<'de, WMIError> for ValueType {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        self
+    }
+}
+
+impl<'de> Deserializer<'de> for ValueType {
+    type Error = WMIError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self {
+            ValueType::EMPTY => visitor.visit_unit(),
+
+            #[cfg(windows)]
+            ValueType::CIM_OBJECT(obj) => {
+                let properties = obj.get_properties(true)
+                    .map_err(|e| WMIError::PropertyReadFailed(e.to_string()))?;
+
+                visitor.visit_map(MapDeserializer::new(properties.unwrap_or_default().into_iter()))
+            }
+
+            ValueType::BSTR(v) => visitor.visit_string(v),
+            ValueType::I1(v) => visitor.visit_i8(v),
+            ValueType::I2(v) => visitor.visit_i16(v),
+            ValueType::I4(v) => visitor.visit_i32(v),
+            ValueType::I8(v) => visitor.visit_i64(v),
+            ValueType::UI1(v) => visitor.visit_u8(v),
+            ValueType::UI2(v) => visitor.visit_u16(v),
+            ValueType::UI4(v) => visitor.visit_u32(v),
+            ValueType::UI8(v) => visitor.visit_u64(v),
+            ValueType::R4(v) => visitor.visit_f32(v),
+            ValueType::R8(v) => visitor.visit_f64(v),
+            ValueType::BOOL(v) => visitor.visit_bool(v),
+
+            #[cfg(windows)]
+            ValueType::CIM_OBJECT_ARRAY(v) => visitor.visit_seq(SeqDeserializer::new(v.into_iter().map(ValueType::CIM_OBJECT))),
+            ValueType::BSTR_ARRAY(v) => visitor.visit_seq(SeqDeserializer::new(v.into_iter())),
+            ValueType::I1_ARRAY(v) => visitor.visit_seq(SeqDeserializer::new(v.into_iter())),
+            ValueType::I2_ARRAY(v) => visitor.visit_seq(SeqDeserializer::new(v.into_iter())),
+            ValueType::I4_ARRAY(v) => visitor.visit_seq(SeqDeserializer::new(v.into_iter())),
+            ValueType::I8_ARRAY(v) => visitor.visit_seq(SeqDeserializer::new(v.into_iter())),
+            ValueType::UI1_ARRAY(v) => visitor.visit_seq(SeqDeserializer::new(v.into_iter())),
+            ValueType::UI2_ARRAY(v) => visitor.visit_seq(SeqDeserializer::new(v.into_iter())),
+            ValueType::UI4_ARRAY(v) => visitor.visit_seq(SeqDeserializer::new(v.into_iter())),
+            ValueType::UI8_ARRAY(v) => visitor.visit_seq(SeqDeserializer::new(v.into_iter())),
+            ValueType::R4_ARRAY(v) => visitor.visit_seq(SeqDeserializer::new(v.into_iter())),
+            ValueType::R8_ARRAY(v) => visitor.visit_seq(SeqDeserializer::new(v.into_iter())),
+            ValueType::BOOL_ARRAY(v) => visitor.visit_seq(SeqDeserializer::new(v.into_iter()))
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self {
+            ValueType::EMPTY => visitor.visit_none(),
+            v => visitor.visit_some(v)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, WMIError> for Value {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        self
+    }
+}
+
+impl<'de> Deserializer<'de> for Value {
+    type Error = WMIError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self {
+            Value::Null => visitor.visit_unit(),
+            Value::Bool(v) => visitor.visit_bool(v),
+            Value::Int(v) => visitor.visit_i64(v),
+            Value::UInt(v) => visitor.visit_u64(v),
+            Value::Float(v) => visitor.visit_f64(v),
+            Value::String(v) | Value::Datetime(v) | Value::Reference(v) => visitor.visit_string(v),
+            Value::Array(v) => visitor.visit_seq(SeqDeserializer::new(v.into_iter())),
+            Value::Object(v) => visitor.visit_map(MapDeserializer::new(v.into_iter()))
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self {
+            Value::Null => visitor.visit_none(),
+            v => visitor.visit_some(v)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{datetime::CimDateTime, Win32_Process::Win32_Process};
+
+    use std::{collections::BTreeMap, time::Duration};
+
+    use serde::Deserialize;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(rename_all = "PascalCase")]
+    struct Process {
+        #[serde(rename = "ProcessId")]
+        pid: u32,
+        name: String,
+        command_line: Option<String>,
+        executable_path: Option<String>,
+        #[serde(default)]
+        thread_ids: Vec<u32>,
+        creation_date: Option<CimDateTime>
+    }
+
+    fn properties(values: Vec<(&str, ValueType)>) -> HashMap<String, ValueType> {
+        values.into_iter().map(|(k, v)| (k.to_string(), v)).collect()
+    }
+
+    fn object(values: Vec<(&str, Value)>) -> Value {
+        Value::Object(values.into_iter().map(|(k, v)| (k.to_string(), v)).collect::<BTreeMap<_, _>>())
+    }
+
+    #[test]
+    fn properties_into_a_struct() {
+        let process: Process = from_properties(properties(vec![
+            ("ProcessId", ValueType::UI4(42)),
+            ("Name", ValueType::BSTR("notepad.exe".to_string())),
+            ("CommandLine", ValueType::EMPTY),
+            ("ThreadIds", ValueType::UI4_ARRAY(vec![1, 2])),
+            ("CreationDate", ValueType::BSTR("19700101000000.000000+000".to_string())),
+            // anything the struct doesn't have is skipped
+            ("Priority", ValueType::I4(8))
+        ])).unwrap();
+
+        assert_eq!(process, Process {
+            pid: 42,
+            name: "notepad.exe".to_string(),
+            command_line: None,
+            executable_path: None,
+            thread_ids: vec![1, 2],
+            creation_date: Some(CimDateTime::from_unix_micros(0, 0).unwrap())
+        });
+    }
+
+    #[test]
+    fn missing_fields() {
+        let process: Process = from_properties(properties(vec![
+            ("ProcessId", ValueType::UI4(42)),
+            ("Name", ValueType::BSTR("notepad.exe".to_string()))
+        ])).unwrap();
+
+        assert_eq!(process.thread_ids, Vec::<u32>::new());
+        assert_eq!(process.creation_date, None);
+
+        let error = from_properties::<Process>(properties(vec![("ProcessId", ValueType::UI4(42))])).unwrap_err();
+        assert!(matches!(error, WMIError::Deserialize(v) if v.contains("Name")));
+    }
+
+    #[test]
+    fn integers_widen_and_narrow() {
+        #[derive(Deserialize)]
+        struct Numbers {
+            a: u64,
+            b: i8,
+            c: f64
+        }
+
+        let numbers: Numbers = from_properties(properties(vec![
+            ("a", ValueType::UI4(7)),
+            ("b", ValueType::I4(-3)),
+            ("c", ValueType::R4(0.5))
+        ])).unwrap();
+
+        assert_eq!((numbers.a, numbers.b, numbers.c), (7, -3, 0.5));
+    }
+
+    #[test]
+    fn type_mismatches_are_errors() {
+        let mismatched = |name: ValueType, pid: ValueType| {
+            from_properties::<Process>(properties(vec![("ProcessId", pid), ("Name", name)])).unwrap_err()
+        };
+
+        assert!(matches!(mismatched(ValueType::BSTR("a".to_string()), ValueType::BSTR("42".to_string())), WMIError::Deserialize(_)));
+        assert!(matches!(mismatched(ValueType::UI4(1), ValueType::UI4(42)), WMIError::Deserialize(_)));
+        assert!(matches!(mismatched(ValueType::EMPTY, ValueType::UI4(42)), WMIError::Deserialize(_)));
+        // out of range for a u32
+        assert!(matches!(mismatched(ValueType::BSTR("a".to_string()), ValueType::I4(-1)), WMIError::Deserialize(_)));
+        assert!(matches!(mismatched(ValueType::BSTR("a".to_string()), ValueType::UI8(1 << 40)), WMIError::Deserialize(_)));
+        assert!(matches!(mismatched(ValueType::BSTR("a".to_string()), ValueType::BOOL_ARRAY(vec![true])), WMIError::Deserialize(_)));
+    }
+
+    #[test]
+    fn bad_datetimes_are_errors() {
+        let error = from_properties::<Process>(properties(vec![
+            ("ProcessId", ValueType::UI4(42)),
+            ("Name", ValueType::BSTR("notepad.exe".to_string())),
+            ("CreationDate", ValueType::BSTR("yesterday".to_string()))
+        ])).unwrap_err();
+
+        assert!(matches!(error, WMIError::Deserialize(_)));
+    }
+
+    #[test]
+    fn win32_process() {
+        let process: Win32_Process = from_properties(properties(vec![
+            ("ProcessId", ValueType::UI4(42)),
+            ("Name", ValueType::BSTR("notepad.exe".to_string())),
+            ("UserModeTime", ValueType::UI8(15_000_000)),
+            ("WorkingSetSize", ValueType::UI8(1 << 20))
+        ])).unwrap();
+
+        assert_eq!(process.ProcessId, 42);
+        assert_eq!(process.UserModeTime, Duration::from_millis(1500));
+        assert_eq!(process.WorkingSetSize, 1 << 20);
+        assert_eq!(process.CommandLine, "");
+    }
+
+    #[test]
+    fn values_into_a_struct() {
+        let process: Process = from_value(object(vec![
+            ("ProcessId", Value::UInt(42)),
+            ("Name", Value::String("notepad.exe".to_string())),
+            ("CommandLine", Value::Null),
+            ("ThreadIds", Value::Array(vec![Value::UInt(1), Value::Int(2)])),
+            ("CreationDate", Value::Datetime("19700101000000.000000+000".to_string()))
+        ])).unwrap();
+
+        assert_eq!(process, Process {
+            pid: 42,
+            name: "notepad.exe".to_string(),
+            command_line: None,
+            executable_path: None,
+            thread_ids: vec![1, 2],
+            creation_date: Some(CimDateTime::from_unix_micros(0, 0).unwrap())
+        });
+    }
+
+    #[test]
+    fn nested_values() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Event {
+            #[serde(rename = "TargetInstance")]
+            target: Process
+        }
+
+        let event: Event = from_value(object(vec![
+            ("TargetInstance", object(vec![
+                ("ProcessId", Value::UInt(42)),
+                ("Name", Value::String("notepad.exe".to_string()))
+            ]))
+        ])).unwrap();
+
+        assert_eq!(event.target.pid, 42);
+    }
+
+    #[test]
+    fn value_mismatches_are_errors() {
+        let mismatched = |pid: Value| from_value::<Process>(object(vec![("ProcessId", pid), ("Name", Value::String("a".to_string()))]));
+
+        assert!(matches!(mismatched(Value::String("42".to_string())), Err(WMIError::Deserialize(_))));
+        assert!(matches!(mismatched(Value::Int(-1)), Err(WMIError::Deserialize(_))));
+        assert!(matches!(mismatched(Value::Null), Err(WMIError::Deserialize(_))));
+        assert!(matches!(mismatched(Value::Array(vec![])), Err(WMIError::Deserialize(_))));
+        assert!(matches!(from_value::<Process>(Value::UInt(1)), Err(WMIError::Deserialize(_))));
+    }
+}
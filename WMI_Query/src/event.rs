@@ -0,0 +1,133 @@
+use crate::wql::{prop, Query};
+
+use std::time::Duration;
+
+
+/// The kinds of `__InstanceOperationEvent` WMI sends about an instance
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    Created,
+    Modified,
+    Deleted
+}
+
+impl EventKind {
+    pub const ALL: [EventKind; 3] = [EventKind::Created, EventKind::Modified, EventKind::Deleted];
+
+    /// The system class these events are instances of
+    pub fn class(&self) -> &'static str {
+        match self {
+            EventKind::Created => InstanceCreation::CLASS,
+            EventKind::Modified => InstanceModification::CLASS,
+            EventKind::Deleted => InstanceDeletion::CLASS
+        }
+    }
+
+    pub fn from_class(class: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|v| v.class().eq_ignore_ascii_case(class))
+    }
+}
+
+/// An intrinsic event class, to select from with [`Select::from_event`](crate::wql::Select::from_event)
+pub trait EventClass {
+    const CLASS: &'static str;
+}
+
+/// `__InstanceCreationEvent`
+pub struct InstanceCreation;
+
+/// `__InstanceModificationEvent`
+pub struct InstanceModification;
+
+/// `__InstanceDeletionEvent`
+pub struct InstanceDeletion;
+
+/// `__InstanceOperationEvent`, any of the other three
+pub struct InstanceOperation;
+
+impl EventClass for InstanceCreation {
+    const CLASS: &'static str = "__InstanceCreationEvent";
+}
+
+impl EventClass for InstanceModification {
+    const CLASS: &'static str = "__InstanceModificationEvent";
+}
+
+impl EventClass for InstanceDeletion {
+    const CLASS: &'static str = "__InstanceDeletionEvent";
+}
+
+impl EventClass for InstanceOperation {
+    const CLASS: &'static str = "__InstanceOperationEvent";
+}
+
+/// Something that happened to an instance of `T`
+#[derive(Debug, Clone, PartialEq)]
+pub enum InstanceEvent<T> {
+    Created(T),
+    /// `previous` is how it was before the change, `target` how it is now
+    Modified {
+        previous: T,
+        target: T
+    },
+    /// The instance as it was right before it went away
+    Deleted(T)
+}
+
+impl<T> InstanceEvent<T> {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            InstanceEvent::Created(_) => EventKind::Created,
+            InstanceEvent::Modified { .. } => EventKind::Modified,
+            InstanceEvent::Deleted(_) => EventKind::Deleted
+        }
+    }
+
+    /// The `TargetInstance`, the instance as it is after the event
+    pub fn target(&self) -> &T {
+        match self {
+            InstanceEvent::Created(v) | InstanceEvent::Deleted(v) => v,
+            InstanceEvent::Modified { target, .. } => target
+        }
+    }
+
+    pub fn into_target(self) -> T {
+        match self {
+            InstanceEvent::Created(v) | InstanceEvent::Deleted(v) => v,
+            InstanceEvent::Modified { target, .. } => target
+        }
+    }
+
+    /// Convert the instances, keeping the kind of event
+    pub fn try_map<U, E>(self, mut f: impl FnMut(T) -> Result<U, E>) -> Result<InstanceEvent<U>, E> {
+        Ok(match self {
+            InstanceEvent::Created(v) => InstanceEvent::Created(f(v)?),
+            InstanceEvent::Modified { previous, target } => InstanceEvent::Modified {
+                previous: f(previous)?,
+                target: f(target)?
+            },
+            InstanceEvent::Deleted(v) => InstanceEvent::Deleted(f(v)?)
+        })
+    }
+}
+
+/// A notification query for `kinds` of events about instances of `class`, which WMI polls for every `within`.
+/// No kinds at all means every kind. More conditions can be added to what it returns.
+pub fn event_query(class: &str, kinds: &[EventKind], within: Duration) -> Query {
+    let kinds: Vec<EventKind> = EventKind::ALL.into_iter().filter(|v| kinds.contains(v)).collect();
+
+    match kinds.as_slice() {
+        [kind] => Query::select_all().from(kind.class()).within(within).where_isa(class),
+
+        _ => {
+            let query = Query::select_all().from_event::<InstanceOperation>().within(within).where_isa(class);
+
+            // every kind is what __InstanceOperationEvent gets anyway
+            if kinds.is_empty() || kinds.len() == EventKind::ALL.len() {
+                return query;
+            }
+
+            query.and_any(kinds.iter().map(|v| prop("__CLASS").eq(v.class())))
+        }
+    }
+}
@@ -87,7 +87,7 @@ impl IEventSink_Impl for EventSink {
     unsafe fn SetStatus(
         &self,
         lFlags: c_long,
-        _hResult: HRESULT,
+        hResult: HRESULT,
         _strParam: BSTR,
         _pObjParam: *mut IWbemClassObject
     ) -> HRESULT {
@@ -96,6 +96,12 @@ impl IEventSink_Impl for EventSink {
         // If you do not specify WBEM_FLAG_SEND_STATUS when calling your provider or service method,
         // you are guaranteed to receive one and only one call to SetStatus
         if lFlags == WBEM_STATUS_COMPLETE.0 {
+            // the query stopped because of an error, rather than running out of results
+            if hResult.is_err() {
+                warn!("Async query failed: {hResult:?}");
+                let _ = self.sender.try_send(Err(WMIError::AsyncQueryFailed(hResult.0)));
+            }
+
             debug!("End of async result, closing transmitter");
             self.sender.close();
         }
@@ -0,0 +1,51 @@
+use crate::{utils::WMIError, ObjectWrapper::IWbemClassObjectWrapper};
+
+use windows::Win32::System::Wmi::{IEnumWbemClassObject, WBEM_INFINITE, WBEM_S_FALSE};
+
+
+/// The rows of a synchronous query, fetched one at a time as it's iterated
+pub struct QueryResults {
+    enumerator: IEnumWbemClassObject,
+    done: bool
+}
+
+impl QueryResults {
+    pub fn new(enumerator: IEnumWbemClassObject) -> Self {
+        Self {
+            enumerator,
+            done: false
+        }
+    }
+}
+
+impl Iterator for QueryResults {
+    type Item = Result<IWbemClassObjectWrapper, WMIError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None
+        }
+
+        let mut objs = [None; 1];
+        let mut returned = 0u32;
+
+        let res = unsafe { self.enumerator.Next(WBEM_INFINITE.0, &mut objs, &mut returned) };
+
+        if res.is_err() {
+            // the enumerator can't go on after a failure
+            self.done = true;
+            return Some(Err(WMIError::QueryNextFailed(res.0)))
+        }
+
+        // WBEM_S_FALSE once there's nothing left
+        if res.0 == WBEM_S_FALSE.0 || returned == 0 {
+            self.done = true;
+            return None
+        }
+
+        match objs[0].take() {
+            Some(obj) => Some(Ok(IWbemClassObjectWrapper::new(obj))),
+            None => Some(Err(WMIError::NullPointerResult))
+        }
+    }
+}
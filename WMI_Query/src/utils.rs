@@ -1,3 +1,7 @@
+use crate::wql::ParseError;
+
+use std::fmt::Display;
+
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -12,9 +16,45 @@ pub enum WMIError {
     #[error("Query run failed")]
     QueryRunFailed,
 
+    #[error("Failed to get the next query result -> code: {0:#x}")]
+    QueryNextFailed(i32),
+
     #[error("Unparsable Query")]
     WbemUnparsableQuery,
 
+    #[error("Invalid query -> {0}")]
+    InvalidQuery(ParseError),
+
+    #[error("Async query failed -> code: {0:#x}")]
+    AsyncQueryFailed(i32),
+
     #[error("Property is not a CIM_OBJECT")]
-    NotCimObject
+    NotCimObject,
+
+    #[error("Unsupported type for property {property} -> vartype: {vartype:#x}")]
+    UnsupportedType {
+        property: String,
+        vartype: i32
+    },
+
+    #[error("Property {property} is not a valid number -> {value}")]
+    InvalidNumber {
+        property: String,
+        value: String
+    },
+
+    #[error("Failed to read properties -> {0}")]
+    PropertyReadFailed(String),
+
+    #[error("Failed to deserialize -> {0}")]
+    Deserialize(String),
+
+    #[error("Not an instance event -> class: {0}")]
+    NotInstanceEvent(String)
+}
+
+impl serde::de::Error for WMIError {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::Deserialize(msg.to_string())
+    }
 }
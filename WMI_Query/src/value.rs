@@ -0,0 +1,164 @@
+#[cfg(windows)]
+use crate::ObjectWrapper::IWbemClassObjectWrapper;
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+
+/// The value of a single property of a WMI object
+#[allow(non_camel_case_types)]
+#[non_exhaustive]
+#[derive(Debug)]
+pub enum ValueType {
+    EMPTY,
+    #[cfg(windows)]
+    CIM_OBJECT(IWbemClassObjectWrapper),
+    BSTR(String),
+    I1(i8),
+    I2(i16),
+    I4(i32),
+    I8(i64),
+    UI1(u8),
+    UI2(u16),
+    UI4(u32),
+    UI8(u64),
+    R4(f32),
+    R8(f64),
+    BOOL(bool),
+    #[cfg(windows)]
+    CIM_OBJECT_ARRAY(Vec<IWbemClassObjectWrapper>),
+    BSTR_ARRAY(Vec<String>),
+    I1_ARRAY(Vec<i8>),
+    I2_ARRAY(Vec<i16>),
+    I4_ARRAY(Vec<i32>),
+    I8_ARRAY(Vec<i64>),
+    UI1_ARRAY(Vec<u8>),
+    UI2_ARRAY(Vec<u16>),
+    UI4_ARRAY(Vec<u32>),
+    UI8_ARRAY(Vec<u64>),
+    R4_ARRAY(Vec<f32>),
+    R8_ARRAY(Vec<f64>),
+    BOOL_ARRAY(Vec<bool>)
+}
+
+/// An owned copy of a WMI value that doesn't need COM, to log, compare or test with
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    String(String),
+    /// A CIM_DATETIME as WMI writes it, e.g. `20220314093000.000000+060`
+    Datetime(String),
+    /// A CIM_REFERENCE, the path of another object
+    Reference(String),
+    Array(Vec<Value>),
+    /// An object's properties by name
+    Object(BTreeMap<String, Value>)
+}
+
+impl Value {
+    /// The property `name`, if this is an object that has it
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        match self {
+            Value::Object(v) => v.get(name),
+            _ => None
+        }
+    }
+
+    /// The text of a string, datetime or reference
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(v) | Value::Datetime(v) | Value::Reference(v) => Some(v),
+            _ => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::de;
+
+    use serde_json::json;
+
+    fn process() -> Value {
+        Value::Object(BTreeMap::from([
+            ("Name".to_string(), Value::String("calc.exe".to_string())),
+            ("ProcessId".to_string(), Value::UInt(42)),
+            ("Priority".to_string(), Value::Int(-1)),
+            ("CreationDate".to_string(), Value::Datetime("20220314093000.000000+060".to_string())),
+            ("Path".to_string(), Value::Reference("Win32_Process.Handle=\"42\"".to_string())),
+            ("CommandLine".to_string(), Value::Null),
+            ("Ratio".to_string(), Value::Float(0.5)),
+            ("Critical".to_string(), Value::Bool(false)),
+            ("Modules".to_string(), Value::Array(vec![Value::String("a.dll".to_string()), Value::String("b.dll".to_string())]))
+        ]))
+    }
+
+    #[test]
+    fn get() {
+        let process = process();
+
+        assert_eq!(process.get("ProcessId"), Some(&Value::UInt(42)));
+        assert_eq!(process.get("CommandLine"), Some(&Value::Null));
+        assert_eq!(process.get("processid"), None);
+        assert_eq!(Value::String("Name".to_string()).get("Name"), None);
+        assert_eq!(Value::Null.get("Name"), None);
+    }
+
+    #[test]
+    fn as_str() {
+        let process = process();
+        let text = |name: &str| process.get(name).and_then(Value::as_str);
+
+        assert_eq!(text("Name"), Some("calc.exe"));
+        assert_eq!(text("CreationDate"), Some("20220314093000.000000+060"));
+        assert_eq!(text("Path"), Some("Win32_Process.Handle=\"42\""));
+        assert_eq!(text("ProcessId"), None);
+        assert_eq!(text("CommandLine"), None);
+        assert_eq!(text("Modules"), None);
+    }
+
+    #[test]
+    fn serializes_untagged() {
+        assert_eq!(serde_json::to_value(process()).unwrap(), json!({
+            "Name": "calc.exe",
+            "ProcessId": 42,
+            "Priority": -1,
+            "CreationDate": "20220314093000.000000+060",
+            "Path": "Win32_Process.Handle=\"42\"",
+            "CommandLine": null,
+            "Ratio": 0.5,
+            "Critical": false,
+            "Modules": ["a.dll", "b.dll"]
+        }));
+
+        assert_eq!(serde_json::to_string(&Value::Array(Vec::new())).unwrap(), "[]");
+        assert_eq!(serde_json::to_string(&Value::Object(BTreeMap::new())).unwrap(), "{}");
+        assert_eq!(serde_json::to_string(&Value::UInt(u64::MAX)).unwrap(), u64::MAX.to_string());
+    }
+
+    #[test]
+    fn deserializes_into_anything() {
+        // the other way around from serializing, through its own deserializer
+        let json: serde_json::Value = de::from_value(process()).unwrap();
+        assert_eq!(json, serde_json::to_value(process()).unwrap());
+
+        assert_eq!(de::from_value::<Option<u32>>(Value::Null).unwrap(), None);
+        assert_eq!(de::from_value::<Vec<bool>>(Value::Array(vec![Value::Bool(true)])).unwrap(), [true]);
+    }
+
+    #[test]
+    fn clones_compare_equal() {
+        let process = process();
+
+        assert_eq!(process.clone(), process);
+        assert_ne!(Value::Int(1), Value::UInt(1));
+        assert_ne!(Value::String("a".to_string()), Value::Datetime("a".to_string()));
+    }
+}
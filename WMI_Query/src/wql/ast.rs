@@ -0,0 +1,230 @@
+use std::fmt;
+
+
+/// A parsed WQL `SELECT`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    pub select: Selection,
+    pub from: String,
+    /// Seconds between polls for intrinsic events
+    pub within: Option<f64>,
+    pub condition: Option<Expr>,
+    pub group: Option<Group>
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Selection {
+    /// `*`
+    All,
+    Properties(Vec<String>)
+}
+
+/// `GROUP WITHIN seconds [BY properties] [HAVING condition]`, which sends one event for each batch
+#[derive(Debug, Clone, PartialEq)]
+pub struct Group {
+    pub within: f64,
+    pub by: Vec<String>,
+    pub having: Option<Expr>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare {
+        left: Operand,
+        op: CompareOp,
+        right: Operand
+    },
+    /// `property ISA 'class'`
+    Isa {
+        property: String,
+        class: String
+    },
+    /// `property [NOT] LIKE 'pattern'`
+    Like {
+        property: String,
+        pattern: String,
+        negated: bool
+    },
+    /// `property IS [NOT] NULL`
+    IsNull {
+        property: String,
+        negated: bool
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    NotEq,
+    Less,
+    LessEq,
+    Greater,
+    GreaterEq
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    /// A property name, with `.` between the parts for embedded objects like `TargetInstance.Name`
+    Property(String),
+    Literal(Literal)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    String(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Null
+}
+
+impl Expr {
+    /// Both have to hold
+    pub fn and(self, other: Expr) -> Expr {
+        Expr::And(Box::new(self), Box::new(other))
+    }
+
+    /// Either has to hold
+    pub fn or(self, other: Expr) -> Expr {
+        Expr::Or(Box::new(self), Box::new(other))
+    }
+
+    /// How tightly it binds, to know where parentheses are needed
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Or(..) => 1,
+            Expr::And(..) => 2,
+            Expr::Not(_) => 3,
+            _ => 4
+        }
+    }
+}
+
+/// A WQL string literal, with `\` and quotes escaped
+pub fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SELECT {} FROM {}", self.select, self.from)?;
+
+        if let Some(within) = self.within {
+            write!(f, " WITHIN {within}")?;
+        }
+
+        if let Some(condition) = &self.condition {
+            write!(f, " WHERE {condition}")?;
+        }
+
+        if let Some(group) = &self.group {
+            write!(f, " GROUP WITHIN {}", group.within)?;
+
+            if !group.by.is_empty() {
+                write!(f, " BY {}", group.by.join(", "))?;
+            }
+
+            if let Some(having) = &group.having {
+                write!(f, " HAVING {having}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Selection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Selection::All => write!(f, "*"),
+            Selection::Properties(v) => write!(f, "{}", v.join(", "))
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // a looser child than its parent needs parentheses to keep its meaning
+        let child = |f: &mut fmt::Formatter<'_>, expr: &Expr| {
+            if expr.precedence() < self.precedence() {
+                write!(f, "({expr})")
+            } else {
+                write!(f, "{expr}")
+            }
+        };
+
+        match self {
+            Expr::And(left, right) => {
+                child(f, left)?;
+                write!(f, " AND ")?;
+                child(f, right)
+            }
+
+            Expr::Or(left, right) => {
+                child(f, left)?;
+                write!(f, " OR ")?;
+                child(f, right)
+            }
+
+            Expr::Not(expr) => {
+                write!(f, "NOT ")?;
+                child(f, expr)
+            }
+
+            Expr::Compare { left, op, right } => write!(f, "{left} {op} {right}"),
+            Expr::Isa { property, class } => write!(f, "{property} ISA {}", quote(class)),
+
+            Expr::Like { property, pattern, negated } => {
+                let not = if *negated { "NOT " } else { "" };
+                write!(f, "{property} {not}LIKE {}", quote(pattern))
+            }
+
+            Expr::IsNull { property, negated } => {
+                let not = if *negated { "NOT " } else { "" };
+                write!(f, "{property} IS {not}NULL")
+            }
+        }
+    }
+}
+
+impl fmt::Display for CompareOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            CompareOp::Eq => "=",
+            CompareOp::NotEq => "<>",
+            CompareOp::Less => "<",
+            CompareOp::LessEq => "<=",
+            CompareOp::Greater => ">",
+            CompareOp::GreaterEq => ">="
+        };
+
+        write!(f, "{op}")
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Property(v) => write!(f, "{v}"),
+            Operand::Literal(v) => write!(f, "{v}")
+        }
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Literal::String(v) => write!(f, "{}", quote(v)),
+            Literal::Int(v) => write!(f, "{v}"),
+            // a whole float keeps its point, so it doesn't come back as an integer
+            Literal::Float(v) if v.fract() == 0.0 && v.is_finite() => write!(f, "{v:.1}"),
+            Literal::Float(v) => write!(f, "{v}"),
+            Literal::Bool(true) => write!(f, "TRUE"),
+            Literal::Bool(false) => write!(f, "FALSE"),
+            Literal::Null => write!(f, "NULL")
+        }
+    }
+}
@@ -0,0 +1,324 @@
+use super::ast::{CompareOp, Expr, Literal, Operand, Query, Selection};
+use crate::{de::WMIClass, event::EventClass};
+
+use std::{ops::Not, time::Duration};
+
+
+/// The first half of a query, before it says what it's selecting from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Select {
+    selection: Selection
+}
+
+impl Query {
+    /// `SELECT properties`
+    pub fn select<I, S>(properties: I) -> Select
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>
+    {
+        Select {
+            selection: Selection::Properties(properties.into_iter().map(Into::into).collect())
+        }
+    }
+
+    /// `SELECT *`
+    pub fn select_all() -> Select {
+        Select {
+            selection: Selection::All
+        }
+    }
+
+    /// How often WMI checks for intrinsic events like `__InstanceCreationEvent`
+    pub fn within(mut self, interval: Duration) -> Self {
+        self.within = Some(interval.as_secs_f64());
+        self
+    }
+
+    /// Only events about instances of `class`, `TargetInstance ISA 'class'`
+    pub fn where_isa(self, class: &str) -> Self {
+        self.and(prop("TargetInstance").isa(class))
+    }
+
+    /// `condition` has to hold, along with any there already are
+    pub fn and(mut self, condition: Expr) -> Self {
+        self.condition = Some(match self.condition.take() {
+            Some(v) => v.and(condition),
+            None => condition
+        });
+
+        self
+    }
+
+    /// At least one of `conditions` has to hold. Nothing at all leaves the query as it is.
+    pub fn and_any(self, conditions: impl IntoIterator<Item = Expr>) -> Self {
+        match conditions.into_iter().reduce(Expr::or) {
+            Some(v) => self.and(v),
+            None => self
+        }
+    }
+}
+
+impl Select {
+    pub fn from(self, class: &str) -> Query {
+        Query {
+            select: self.selection,
+            from: class.to_string(),
+            within: None,
+            condition: None,
+            group: None
+        }
+    }
+
+    /// Instances of `T`'s class, or its extrinsic events like `Win32_ProcessStartTrace`
+    pub fn from_class<T: WMIClass>(self) -> Query {
+        self.from(T::CLASS)
+    }
+
+    /// Intrinsic events, like [`InstanceCreation`](crate::event::InstanceCreation)
+    pub fn from_event<E: EventClass>(self) -> Query {
+        self.from(E::CLASS)
+    }
+}
+
+/// `!condition` for `NOT condition`
+impl Not for Expr {
+    type Output = Expr;
+
+    fn not(self) -> Expr {
+        Expr::Not(Box::new(self))
+    }
+}
+
+/// A property to build a condition on, like `prop("TargetInstance.Name").like("%tel%")`
+pub fn prop(name: &str) -> Prop {
+    Prop(name.to_string())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Prop(String);
+
+impl Prop {
+    fn compare(self, op: CompareOp, value: impl Into<Operand>) -> Expr {
+        Expr::Compare {
+            left: Operand::Property(self.0),
+            op,
+            right: value.into()
+        }
+    }
+
+    pub fn eq(self, value: impl Into<Operand>) -> Expr {
+        self.compare(CompareOp::Eq, value)
+    }
+
+    pub fn ne(self, value: impl Into<Operand>) -> Expr {
+        self.compare(CompareOp::NotEq, value)
+    }
+
+    pub fn lt(self, value: impl Into<Operand>) -> Expr {
+        self.compare(CompareOp::Less, value)
+    }
+
+    pub fn le(self, value: impl Into<Operand>) -> Expr {
+        self.compare(CompareOp::LessEq, value)
+    }
+
+    pub fn gt(self, value: impl Into<Operand>) -> Expr {
+        self.compare(CompareOp::Greater, value)
+    }
+
+    pub fn ge(self, value: impl Into<Operand>) -> Expr {
+        self.compare(CompareOp::GreaterEq, value)
+    }
+
+    /// `%` is anything and `_` any one character, see [`like_escape`] to match them as they are
+    pub fn like(self, pattern: &str) -> Expr {
+        Expr::Like {
+            property: self.0,
+            pattern: pattern.to_string(),
+            negated: false
+        }
+    }
+
+    pub fn not_like(self, pattern: &str) -> Expr {
+        Expr::Like {
+            property: self.0,
+            pattern: pattern.to_string(),
+            negated: true
+        }
+    }
+
+    pub fn isa(self, class: &str) -> Expr {
+        Expr::Isa {
+            property: self.0,
+            class: class.to_string()
+        }
+    }
+
+    pub fn is_null(self) -> Expr {
+        Expr::IsNull {
+            property: self.0,
+            negated: false
+        }
+    }
+
+    pub fn is_not_null(self) -> Expr {
+        Expr::IsNull {
+            property: self.0,
+            negated: true
+        }
+    }
+}
+
+/// Wrap `[`, `%` and `_` in brackets, so `LIKE` matches `value` as it is
+pub fn like_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            '[' | '%' | '_' => {
+                escaped.push('[');
+                escaped.push(c);
+                escaped.push(']');
+            }
+
+            c => escaped.push(c)
+        }
+    }
+
+    escaped
+}
+
+/// Compare against another property
+impl From<Prop> for Operand {
+    fn from(prop: Prop) -> Self {
+        Operand::Property(prop.0)
+    }
+}
+
+impl<T: Into<Literal>> From<T> for Operand {
+    fn from(value: T) -> Self {
+        Operand::Literal(value.into())
+    }
+}
+
+impl From<&str> for Literal {
+    fn from(value: &str) -> Self {
+        Literal::String(value.to_string())
+    }
+}
+
+impl From<String> for Literal {
+    fn from(value: String) -> Self {
+        Literal::String(value)
+    }
+}
+
+impl From<bool> for Literal {
+    fn from(value: bool) -> Self {
+        Literal::Bool(value)
+    }
+}
+
+impl From<f64> for Literal {
+    fn from(value: f64) -> Self {
+        Literal::Float(value)
+    }
+}
+
+macro_rules! int_literal {
+    ($($t:ty),*) => {
+        $(
+            impl From<$t> for Literal {
+                fn from(value: $t) -> Self {
+                    Literal::Int(value as i64)
+                }
+            }
+        )*
+    };
+}
+
+int_literal!(i8, i16, i32, i64, u8, u16, u32);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{event::InstanceCreation, wql::parse, Win32_Process::Win32_Process};
+
+    /// Renders as `wql`, and parses back into the same query
+    fn renders(query: Query, wql: &str) {
+        assert_eq!(query.to_string(), wql);
+        assert_eq!(parse(wql).unwrap(), query);
+    }
+
+    #[test]
+    fn select() {
+        renders(Query::select(["Name", "ProcessId"]).from("Win32_Process"), "SELECT Name, ProcessId FROM Win32_Process");
+        renders(Query::select_all().from_class::<Win32_Process>(), "SELECT * FROM Win32_Process");
+    }
+
+    #[test]
+    fn events() {
+        renders(
+            Query::select_all().from_event::<InstanceCreation>().within(Duration::from_millis(500)).where_isa("Win32_Process"),
+            "SELECT * FROM __InstanceCreationEvent WITHIN 0.5 WHERE TargetInstance ISA 'Win32_Process'"
+        );
+
+        renders(
+            Query::select_all().from_event::<InstanceCreation>().within(Duration::from_secs(2)),
+            "SELECT * FROM __InstanceCreationEvent WITHIN 2"
+        );
+    }
+
+    #[test]
+    fn and_any() {
+        let names = ["a.exe", "b.exe", "c.exe"].map(|v| prop("TargetInstance.Name").eq(v));
+
+        renders(
+            Query::select_all().from("__InstanceCreationEvent").where_isa("Win32_Process").and_any(names),
+            "SELECT * FROM __InstanceCreationEvent WHERE TargetInstance ISA 'Win32_Process' AND \
+             (TargetInstance.Name = 'a.exe' OR TargetInstance.Name = 'b.exe' OR TargetInstance.Name = 'c.exe')"
+        );
+
+        // one alone needs no parentheses, and none at all adds nothing
+        renders(Query::select_all().from("A").and_any([prop("B").gt(1)]), "SELECT * FROM A WHERE B > 1");
+        renders(Query::select_all().from("A").and(prop("B").is_null()).and_any([]), "SELECT * FROM A WHERE B IS NULL");
+    }
+
+    #[test]
+    fn not() {
+        renders(
+            Query::select_all().from("A").and(!(prop("B").eq(1).or(prop("C").is_not_null()))),
+            "SELECT * FROM A WHERE NOT (B = 1 OR C IS NOT NULL)"
+        );
+    }
+
+    #[test]
+    fn like_escapes() {
+        assert_eq!(like_escape("50%_off[1].exe"), "50[%][_]off[[]1].exe");
+        assert_eq!(like_escape("plain.exe"), "plain.exe");
+
+        renders(
+            Query::select_all().from("Win32_Process").and(prop("Name").like(&format!("{}%", like_escape("a_b")))),
+            "SELECT * FROM Win32_Process WHERE Name LIKE 'a[_]b%'"
+        );
+    }
+
+    #[test]
+    fn quotes() {
+        renders(
+            Query::select_all().from("Win32_Process").and(prop("Name").eq("it's")),
+            r"SELECT * FROM Win32_Process WHERE Name = 'it\'s'"
+        );
+
+        renders(
+            Query::select_all().from("Win32_Process").and(prop("ExecutablePath").like(r"C:\Windows\%")),
+            r"SELECT * FROM Win32_Process WHERE ExecutablePath LIKE 'C:\\Windows\\%'"
+        );
+
+        renders(
+            Query::select_all().from("Win32_Process").and(prop("Name").not_like(r"\'")),
+            r"SELECT * FROM Win32_Process WHERE Name NOT LIKE '\\\''"
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use super::{ast::CompareOp, ParseError};
+
+use std::fmt;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    /// A keyword, class or property name
+    Word(String),
+    String(String),
+    /// Left as written, since it could be an integer or not
+    Number(String),
+    Star,
+    Comma,
+    Dot,
+    LParen,
+    RParen,
+    Op(CompareOp),
+    End
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(v) | Token::Number(v) => write!(f, "`{v}`"),
+            Token::String(v) => write!(f, "{}", super::ast::quote(v)),
+            Token::Star => write!(f, "`*`"),
+            Token::Comma => write!(f, "`,`"),
+            Token::Dot => write!(f, "`.`"),
+            Token::LParen => write!(f, "`(`"),
+            Token::RParen => write!(f, "`)`"),
+            Token::Op(v) => write!(f, "`{v}`"),
+            Token::End => write!(f, "the end of the query")
+        }
+    }
+}
+
+/// Split `query` up into tokens, each with the byte it starts at. The last one is always `End`.
+pub fn tokenize(query: &str) -> Result<Vec<(Token, usize)>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = query.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let next_is_digit = query[start + c.len_utf8()..].starts_with(|c: char| c.is_ascii_digit());
+
+        let token = match c {
+            '*' | ',' | '.' | '(' | ')' => {
+                chars.next();
+
+                match c {
+                    '*' => Token::Star,
+                    ',' => Token::Comma,
+                    '.' => Token::Dot,
+                    '(' => Token::LParen,
+                    _ => Token::RParen
+                }
+            }
+
+            '=' | '<' | '>' | '!' => {
+                chars.next();
+                let second = chars.peek().map(|&(_, c)| c);
+
+                let (op, long) = match (c, second) {
+                    ('<', Some('>')) | ('!', Some('=')) => (CompareOp::NotEq, true),
+                    ('<', Some('=')) => (CompareOp::LessEq, true),
+                    ('>', Some('=')) => (CompareOp::GreaterEq, true),
+                    ('<', _) => (CompareOp::Less, false),
+                    ('>', _) => (CompareOp::Greater, false),
+                    ('=', _) => (CompareOp::Eq, false),
+                    _ => return Err(ParseError::at(query, start, "expected `!=`"))
+                };
+
+                if long {
+                    chars.next();
+                }
+
+                Token::Op(op)
+            }
+
+            '\'' | '"' => {
+                chars.next();
+                let mut value = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some((_, v)) if v == c => break,
+                        // a backslash lets the next character through as it is, quotes included
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, v)) => value.push(v),
+                            None => return Err(ParseError::at(query, start, "unterminated string"))
+                        },
+                        Some((_, v)) => value.push(v),
+                        None => return Err(ParseError::at(query, start, "unterminated string"))
+                    }
+                }
+
+                Token::String(value)
+            }
+
+            v if v.is_ascii_digit() || (v == '-' && next_is_digit) => {
+                chars.next();
+                let mut end = start + 1;
+
+                while let Some(&(i, v)) = chars.peek() {
+                    if !v.is_ascii_digit() && v != '.' {
+                        break;
+                    }
+
+                    chars.next();
+                    end = i + 1;
+                }
+
+                let number = &query[start..end];
+                if number.matches('.').count() > 1 || number.ends_with('.') {
+                    return Err(ParseError::at(query, start, format!("invalid number `{number}`")));
+                }
+
+                Token::Number(number.to_string())
+            }
+
+            v if v.is_alphabetic() || v == '_' => {
+                let mut end = start;
+
+                while let Some(&(i, v)) = chars.peek() {
+                    if !v.is_alphanumeric() && v != '_' {
+                        break;
+                    }
+
+                    chars.next();
+                    end = i + v.len_utf8();
+                }
+
+                Token::Word(query[start..end].to_string())
+            }
+
+            v => return Err(ParseError::at(query, start, format!("unexpected character `{v}`")))
+        };
+
+        tokens.push((token, start));
+    }
+
+    tokens.push((Token::End, query.len()));
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(query: &str) -> Vec<(Token, usize)> {
+        tokenize(query).unwrap()
+    }
+
+    fn word(v: &str) -> Token {
+        Token::Word(v.to_string())
+    }
+
+    #[test]
+    fn tokens_and_offsets() {
+        assert_eq!(tokens("SELECT * FROM Win32_Process"), vec![
+            (word("SELECT"), 0),
+            (Token::Star, 7),
+            (word("FROM"), 9),
+            (word("Win32_Process"), 14),
+            (Token::End, 27)
+        ]);
+    }
+
+    #[test]
+    fn operators() {
+        let ops: Vec<Token> = tokens("= <> != < <= > >=").into_iter().map(|(t, _)| t).collect();
+
+        assert_eq!(ops, vec![
+            Token::Op(CompareOp::Eq),
+            Token::Op(CompareOp::NotEq),
+            Token::Op(CompareOp::NotEq),
+            Token::Op(CompareOp::Less),
+            Token::Op(CompareOp::LessEq),
+            Token::Op(CompareOp::Greater),
+            Token::Op(CompareOp::GreaterEq),
+            Token::End
+        ]);
+    }
+
+    #[test]
+    fn operators_need_no_spaces() {
+        assert_eq!(tokens("a<=1")[1], (Token::Op(CompareOp::LessEq), 1));
+        assert_eq!(tokens("a<>'b'")[2], (Token::String("b".to_string()), 3));
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(tokens("'a b'")[0].0, Token::String("a b".to_string()));
+        assert_eq!(tokens("\"double\"")[0].0, Token::String("double".to_string()));
+        assert_eq!(tokens(r"'it\'s'")[0].0, Token::String("it's".to_string()));
+        assert_eq!(tokens(r"'C:\\Windows'")[0].0, Token::String(r"C:\Windows".to_string()));
+        // the other kind of quote is just a character
+        assert_eq!(tokens("'say \"hi\"'")[0].0, Token::String("say \"hi\"".to_string()));
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(tokens("12")[0].0, Token::Number("12".to_string()));
+        assert_eq!(tokens("-3.5")[0].0, Token::Number("-3.5".to_string()));
+        assert_eq!(tokens("0.25")[0].0, Token::Number("0.25".to_string()));
+    }
+
+    #[test]
+    fn offsets_are_in_bytes() {
+        assert_eq!(tokens("'é' x")[1], (word("x"), 5));
+    }
+
+    #[test]
+    fn errors() {
+        let error = |query: &str| tokenize(query).unwrap_err();
+
+        assert_eq!(error("a = 'abc"), ParseError { column: 5, message: "unterminated string".to_string() });
+        assert_eq!(error(r"a = 'abc\"), ParseError { column: 5, message: "unterminated string".to_string() });
+        assert_eq!(error("a ! b"), ParseError { column: 3, message: "expected `!=`".to_string() });
+        assert_eq!(error("1.2.3"), ParseError { column: 1, message: "invalid number `1.2.3`".to_string() });
+        assert_eq!(error("12."), ParseError { column: 1, message: "invalid number `12.`".to_string() });
+        assert_eq!(error("é = #"), ParseError { column: 5, message: "unexpected character `#`".to_string() });
+    }
+}
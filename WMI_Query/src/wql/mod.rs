@@ -0,0 +1,40 @@
+mod ast;
+mod builder;
+mod lexer;
+mod parser;
+
+pub use ast::{quote, CompareOp, Expr, Group, Literal, Operand, Query, Selection};
+pub use builder::{like_escape, prop, Prop, Select};
+pub use parser::parse;
+
+use std::str::FromStr;
+
+use thiserror::Error;
+
+
+/// Why a query didn't parse, and where
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[error("{message} at column {column}")]
+pub struct ParseError {
+    /// Counted in characters from 1
+    pub column: usize,
+    pub message: String
+}
+
+impl ParseError {
+    /// An error at byte `offset` of `query`
+    fn at(query: &str, offset: usize, message: impl Into<String>) -> Self {
+        Self {
+            column: query[..offset].chars().count() + 1,
+            message: message.into()
+        }
+    }
+}
+
+impl FromStr for Query {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse(s)
+    }
+}
@@ -0,0 +1,475 @@
+use super::{
+    ast::{Expr, Group, Literal, Operand, Query, Selection},
+    lexer::{self, Token},
+    ParseError
+};
+
+
+/// Words that can't be used as a class or property name
+const KEYWORDS: &[&str] = &[
+    "SELECT", "FROM", "WHERE", "WITHIN", "GROUP", "BY", "HAVING",
+    "AND", "OR", "NOT", "ISA", "LIKE", "IS", "NULL", "TRUE", "FALSE"
+];
+
+/// Parse a WQL `SELECT`, checking it as far as can be done without asking WMI
+pub fn parse(query: &str) -> Result<Query, ParseError> {
+    let mut parser = Parser {
+        query,
+        tokens: lexer::tokenize(query)?,
+        pos: 0
+    };
+
+    let parsed = parser.query()?;
+
+    if parser.peek() != &Token::End {
+        return Err(parser.error(format!("expected the end of the query, found {}", parser.peek())));
+    }
+
+    Ok(parsed)
+}
+
+struct Parser<'a> {
+    query: &'a str,
+    tokens: Vec<(Token, usize)>,
+    pos: usize
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].0
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.pos].0.clone();
+
+        // End stays put, so running off the end can't panic
+        if token != Token::End {
+            self.pos += 1;
+        }
+
+        token
+    }
+
+    /// An error at the token about to be read
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        ParseError::at(self.query, self.tokens[self.pos].1, message)
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Token::Word(v) if v.eq_ignore_ascii_case(keyword))
+    }
+
+    /// Skip past `keyword` if it's next
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found = self.is_keyword(keyword);
+        if found {
+            self.next();
+        }
+
+        found
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), ParseError> {
+        if self.keyword(keyword) {
+            return Ok(());
+        }
+
+        Err(self.error(format!("expected `{keyword}`, found {}", self.peek())))
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), ParseError> {
+        if self.peek() == &token {
+            self.next();
+            return Ok(());
+        }
+
+        Err(self.error(format!("expected {token}, found {}", self.peek())))
+    }
+
+    fn query(&mut self) -> Result<Query, ParseError> {
+        self.expect_keyword("SELECT")?;
+
+        let select = if self.peek() == &Token::Star {
+            self.next();
+            Selection::All
+        } else {
+            Selection::Properties(self.property_list()?)
+        };
+
+        self.expect_keyword("FROM")?;
+        let from = self.name("a class name")?;
+
+        let within = match self.keyword("WITHIN") {
+            true => Some(self.seconds()?),
+            false => None
+        };
+
+        let condition = match self.keyword("WHERE") {
+            true => Some(self.expr()?),
+            false => None
+        };
+
+        let group = match self.keyword("GROUP") {
+            true => Some(self.group()?),
+            false => None
+        };
+
+        if self.is_keyword("HAVING") {
+            return Err(self.error("`HAVING` needs a `GROUP WITHIN` before it"));
+        }
+
+        Ok(Query {
+            select,
+            from,
+            within,
+            condition,
+            group
+        })
+    }
+
+    fn group(&mut self) -> Result<Group, ParseError> {
+        self.expect_keyword("WITHIN")?;
+        let within = self.seconds()?;
+
+        let by = match self.keyword("BY") {
+            true => self.property_list()?,
+            false => Vec::new()
+        };
+
+        let having = match self.keyword("HAVING") {
+            true => Some(self.expr()?),
+            false => None
+        };
+
+        Ok(Group {
+            within,
+            by,
+            having
+        })
+    }
+
+    /// A positive number of seconds, for `WITHIN`
+    fn seconds(&mut self) -> Result<f64, ParseError> {
+        let seconds = match self.peek() {
+            Token::Number(v) => v.parse::<f64>().ok(),
+            _ => None
+        };
+
+        match seconds {
+            Some(v) if v > 0.0 && v.is_finite() => {
+                self.next();
+                Ok(v)
+            }
+
+            Some(_) => Err(self.error("`WITHIN` has to be more than 0 seconds")),
+            None => Err(self.error(format!("expected a number of seconds, found {}", self.peek())))
+        }
+    }
+
+    /// A class or property name that isn't a keyword
+    fn name(&mut self, what: &str) -> Result<String, ParseError> {
+        match self.peek() {
+            Token::Word(v) if !KEYWORDS.iter().any(|k| k.eq_ignore_ascii_case(v)) => {
+                let name = v.clone();
+                self.next();
+                Ok(name)
+            }
+
+            v => Err(self.error(format!("expected {what}, found {v}")))
+        }
+    }
+
+    /// A property, or a property of an embedded object like `TargetInstance.Name`
+    fn property(&mut self) -> Result<String, ParseError> {
+        let mut property = self.name("a property name")?;
+
+        while self.peek() == &Token::Dot {
+            self.next();
+            property.push('.');
+            property.push_str(&self.name("a property name")?);
+        }
+
+        Ok(property)
+    }
+
+    fn property_list(&mut self) -> Result<Vec<String>, ParseError> {
+        let mut properties = vec![self.property()?];
+
+        while self.peek() == &Token::Comma {
+            self.next();
+            properties.push(self.property()?);
+        }
+
+        Ok(properties)
+    }
+
+    fn expr(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.and_expr()?;
+
+        while self.keyword("OR") {
+            expr = expr.or(self.and_expr()?);
+        }
+
+        Ok(expr)
+    }
+
+    fn and_expr(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.not_expr()?;
+
+        while self.keyword("AND") {
+            expr = expr.and(self.not_expr()?);
+        }
+
+        Ok(expr)
+    }
+
+    fn not_expr(&mut self) -> Result<Expr, ParseError> {
+        if self.keyword("NOT") {
+            return Ok(Expr::Not(Box::new(self.not_expr()?)));
+        }
+
+        if self.peek() == &Token::LParen {
+            self.next();
+            let expr = self.expr()?;
+            self.expect(Token::RParen)?;
+
+            return Ok(expr);
+        }
+
+        self.predicate()
+    }
+
+    fn predicate(&mut self) -> Result<Expr, ParseError> {
+        let start = self.pos;
+        let left = self.operand()?;
+
+        if let Operand::Property(property) = &left {
+            let property = property.clone();
+
+            if self.keyword("ISA") {
+                let class = self.string("a class name in quotes")?;
+                return Ok(Expr::Isa { property, class });
+            }
+
+            if self.keyword("IS") {
+                let negated = self.keyword("NOT");
+                self.expect_keyword("NULL")?;
+
+                return Ok(Expr::IsNull { property, negated });
+            }
+
+            let negated = self.is_keyword("NOT");
+            if negated {
+                self.next();
+                if !self.is_keyword("LIKE") {
+                    return Err(self.error(format!("expected `LIKE`, found {}", self.peek())));
+                }
+            }
+
+            if self.keyword("LIKE") {
+                let pattern = self.string("a pattern in quotes")?;
+                return Ok(Expr::Like { property, pattern, negated });
+            }
+        }
+
+        let op = match self.peek() {
+            Token::Op(v) => *v,
+            v => return Err(self.error(format!("expected a comparison, found {v}")))
+        };
+
+        self.next();
+        let right = self.operand()?;
+
+        if matches!((&left, &right), (Operand::Literal(_), Operand::Literal(_))) {
+            return Err(ParseError::at(self.query, self.tokens[start].1, "compares two constants, without any property"));
+        }
+
+        Ok(Expr::Compare { left, op, right })
+    }
+
+    fn operand(&mut self) -> Result<Operand, ParseError> {
+        let literal = match self.peek().clone() {
+            Token::String(v) => Literal::String(v),
+            Token::Number(v) => self.number(&v)?,
+            Token::Word(v) if v.eq_ignore_ascii_case("TRUE") => Literal::Bool(true),
+            Token::Word(v) if v.eq_ignore_ascii_case("FALSE") => Literal::Bool(false),
+            Token::Word(v) if v.eq_ignore_ascii_case("NULL") => Literal::Null,
+            Token::Word(_) => return Ok(Operand::Property(self.property()?)),
+            v => return Err(self.error(format!("expected a property or a value, found {v}")))
+        };
+
+        self.next();
+
+        Ok(Operand::Literal(literal))
+    }
+
+    fn number(&self, number: &str) -> Result<Literal, ParseError> {
+        let literal = if number.contains('.') {
+            number.parse().ok().map(Literal::Float)
+        } else {
+            number.parse().ok().map(Literal::Int)
+        };
+
+        literal.ok_or_else(|| self.error(format!("number `{number}` is out of range")))
+    }
+
+    fn string(&mut self, what: &str) -> Result<String, ParseError> {
+        match self.peek() {
+            Token::String(v) => {
+                let value = v.clone();
+                self.next();
+                Ok(value)
+            }
+
+            v => Err(self.error(format!("expected {what}, found {v}")))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wql::{prop, CompareOp};
+
+    /// The condition of `SELECT * FROM A WHERE {condition}`
+    fn condition(condition: &str) -> Expr {
+        parse(&format!("SELECT * FROM A WHERE {condition}")).unwrap().condition.unwrap()
+    }
+
+    fn error(query: &str) -> (usize, String) {
+        let error = parse(query).unwrap_err();
+        (error.column, error.message)
+    }
+
+    #[test]
+    fn select() {
+        let query = parse("SELECT Name, TargetInstance.ProcessId FROM Win32_Process").unwrap();
+
+        assert_eq!(query.select, Selection::Properties(vec![
+            "Name".to_string(),
+            "TargetInstance.ProcessId".to_string()
+        ]));
+        assert_eq!(query.from, "Win32_Process");
+        assert_eq!(query.within, None);
+        assert_eq!(query.condition, None);
+        assert_eq!(query.group, None);
+    }
+
+    #[test]
+    fn keywords_ignore_case() {
+        let query = parse("select * from A within 0.5 where Name like 'x%'").unwrap();
+
+        assert_eq!(query.select, Selection::All);
+        assert_eq!(query.within, Some(0.5));
+        assert_eq!(query.condition, Some(prop("Name").like("x%")));
+    }
+
+    #[test]
+    fn isa() {
+        assert_eq!(condition("TargetInstance ISA 'Win32_Process'"), prop("TargetInstance").isa("Win32_Process"));
+    }
+
+    #[test]
+    fn like() {
+        assert_eq!(condition("Name LIKE 'note[_]%'"), prop("Name").like("note[_]%"));
+        assert_eq!(condition("Name NOT LIKE 'a%'"), prop("Name").not_like("a%"));
+    }
+
+    #[test]
+    fn is_null() {
+        assert_eq!(condition("Path IS NULL"), prop("Path").is_null());
+        assert_eq!(condition("Path IS NOT NULL"), prop("Path").is_not_null());
+    }
+
+    #[test]
+    fn compare() {
+        assert_eq!(condition("ProcessId >= 4"), prop("ProcessId").ge(4));
+        assert_eq!(condition("ProcessId <> -1"), prop("ProcessId").ne(-1));
+        assert_eq!(condition("Load < 1.5"), prop("Load").lt(1.5));
+        assert_eq!(condition("Enabled = TRUE"), prop("Enabled").eq(true));
+        assert_eq!(condition("'x' = Name"), Expr::Compare {
+            left: Operand::Literal(Literal::String("x".to_string())),
+            op: CompareOp::Eq,
+            right: Operand::Property("Name".to_string())
+        });
+        assert_eq!(condition("Name = Caption"), prop("Name").eq(prop("Caption")));
+        assert_eq!(condition("Path = NULL"), Expr::Compare {
+            left: Operand::Property("Path".to_string()),
+            op: CompareOp::Eq,
+            right: Operand::Literal(Literal::Null)
+        });
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let (a, b, c) = (prop("A").eq(1), prop("B").eq(2), prop("C").eq(3));
+
+        assert_eq!(condition("A = 1 OR B = 2 AND C = 3"), a.clone().or(b.clone().and(c.clone())));
+        assert_eq!(condition("A = 1 AND B = 2 OR C = 3"), a.clone().and(b.clone()).or(c.clone()));
+        assert_eq!(condition("(A = 1 OR B = 2) AND C = 3"), a.clone().or(b.clone()).and(c.clone()));
+        assert_eq!(condition("A = 1 AND B = 2 AND C = 3"), a.clone().and(b.clone()).and(c.clone()));
+        assert_eq!(condition("NOT A = 1 AND B = 2"), (!a.clone()).and(b.clone()));
+        assert_eq!(condition("NOT (A = 1 AND B = 2)"), !a.and(b));
+    }
+
+    #[test]
+    fn group() {
+        let query = parse("SELECT * FROM __InstanceCreationEvent WITHIN 1 WHERE TargetInstance ISA 'Win32_Process' \
+                           GROUP WITHIN 10 BY TargetInstance.Name, TargetInstance.SessionId HAVING NumberOfEvents > 5").unwrap();
+
+        assert_eq!(query.within, Some(1.0));
+        assert_eq!(query.group, Some(Group {
+            within: 10.0,
+            by: vec!["TargetInstance.Name".to_string(), "TargetInstance.SessionId".to_string()],
+            having: Some(prop("NumberOfEvents").gt(5))
+        }));
+
+        let query = parse("SELECT * FROM A GROUP WITHIN 2.5").unwrap();
+        assert_eq!(query.group, Some(Group { within: 2.5, by: Vec::new(), having: None }));
+    }
+
+    #[test]
+    fn round_trips() {
+        let queries = [
+            "SELECT * FROM Win32_Process",
+            "SELECT Name, TargetInstance.ProcessId FROM A WITHIN 0.5",
+            "SELECT * FROM A WHERE A = 1 OR B = 2 AND C = 3",
+            "SELECT * FROM A WHERE (A = 1 OR B = 2) AND NOT (C = 3 OR D IS NOT NULL)",
+            "SELECT * FROM A WHERE Name NOT LIKE 'it\\'s [%]%' AND Path IS NULL AND T ISA 'B'",
+            "SELECT * FROM A WHERE Load >= 2.0 AND Id <> -4 AND Ok = FALSE AND 'x' = Name",
+            "SELECT * FROM A WITHIN 1 WHERE T ISA 'B' GROUP WITHIN 10 BY T.Name HAVING NumberOfEvents > 5",
+            "select * from A where not not A = 1"
+        ];
+
+        for query in queries {
+            let parsed = parse(query).unwrap();
+            let displayed = parsed.to_string();
+
+            assert_eq!(parse(&displayed).unwrap(), parsed, "{query} came back as {displayed}");
+            assert_eq!(parse(&displayed).unwrap().to_string(), displayed);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error("SELECT * FROM A WHERE Name = 'abc"), (30, "unterminated string".to_string()));
+        assert_eq!(error("SELECT * FROM A HAVING X = 1"), (17, "`HAVING` needs a `GROUP WITHIN` before it".to_string()));
+        assert_eq!(error("SELECT * FROM A WITHIN 0"), (24, "`WITHIN` has to be more than 0 seconds".to_string()));
+        assert_eq!(error("SELECT * FROM A GROUP WITHIN -1"), (30, "`WITHIN` has to be more than 0 seconds".to_string()));
+        assert_eq!(error("SELECT * FROM A WHERE X = 1 AND 1 = 2"), (33, "compares two constants, without any property".to_string()));
+        assert_eq!(error("SELECT * FROM A WHERE X = 1 AND 'é' = 'é'"), (33, "compares two constants, without any property".to_string()));
+    }
+
+    #[test]
+    fn other_errors() {
+        assert_eq!(error("SELECT * FROM A WHERE X NOT = 1").0, 29);
+        assert_eq!(error("SELECT * FROM A WHERE X IS 1").0, 28);
+        assert_eq!(error("SELECT * FROM A WHERE T ISA B").0, 29);
+        assert_eq!(error("SELECT * FROM A WHERE (X = 1").0, 29);
+        assert_eq!(error("SELECT * FROM Where").0, 15);
+        assert_eq!(error("SELECT * FROM A B").0, 17);
+        assert_eq!(error("SELECT * FROM A WHERE X = 99999999999999999999").0, 27);
+        assert_eq!(error("SELECT * FROM A GROUP BY X").0, 23);
+        assert_eq!(error("SELECT FROM A").0, 8);
+    }
+}